@@ -0,0 +1,186 @@
+use crate::value::Value;
+
+/// One instruction's operation. Operands, if any, follow it in the chunk's
+/// code as extra bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Pushes the constant whose index is the next byte.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Local variable operations take the variable's stack slot as operand.
+    GetLocal,
+    SetLocal,
+    /// Global variable operations take the index of the variable's name in
+    /// the constant pool as operand.
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    /// Upvalue operations take the index of the upvalue in the current
+    /// closure as operand.
+    GetUpvalue,
+    SetUpvalue,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    /// Jumps take a 16-bit big-endian offset, forwards for `Jump` and
+    /// `JumpIfFalse` and backwards for `Loop`.
+    Jump,
+    JumpIfFalse,
+    Loop,
+    /// Calls the value below the arguments; the operand is the argument
+    /// count.
+    Call,
+    /// Wraps the function constant whose index is the next byte in a closure.
+    /// Then come two bytes per upvalue: whether it captures a local of the
+    /// enclosing function (rather than one of its upvalues), and its index.
+    Closure,
+    /// Moves the local on top of the stack into its upvalue, then pops it.
+    CloseUpvalue,
+    Return,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 31] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+    ];
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// A run of consecutive bytes of code that all came from the same source
+/// line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LineRun {
+    line: u64,
+    length: usize,
+}
+
+/// A sequence of bytecode together with the constants it refers to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// Source line of each byte in `code`, run-length encoded since whole
+    /// runs of instructions usually come from the same line.
+    lines: Vec<LineRun>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: u64) {
+        self.code.push(byte);
+
+        match self.lines.last_mut() {
+            Some(run) if run.line == line => run.length += 1,
+            _ => self.lines.push(LineRun { line, length: 1 }),
+        }
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: u64) {
+        self.write(op as u8, line);
+    }
+
+    /// Adds `value` to the constant pool and returns its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Bytes the chunk's buffers take up on the heap.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.code.capacity()
+            + self.constants.capacity() * size_of::<Value>()
+            + self.lines.capacity() * size_of::<LineRun>()
+    }
+
+    /// Source line of the byte at `offset` in `code`.
+    pub fn line(&self, offset: usize) -> u64 {
+        let mut end = 0;
+        for run in &self.lines {
+            end += run.length;
+            if offset < end {
+                return run.line;
+            }
+        }
+
+        panic!("offset {offset} is past the end of the chunk");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcodes_round_trip_through_bytes() {
+        for (byte, op) in OpCode::ALL.into_iter().enumerate() {
+            assert_eq!(op as usize, byte);
+            assert_eq!(OpCode::try_from(op as u8), Ok(op));
+        }
+        assert_eq!(OpCode::try_from(OpCode::ALL.len() as u8), Err(31));
+    }
+
+    #[test]
+    fn lines_are_run_length_encoded() {
+        let mut chunk = Chunk::new();
+        for line in [1, 1, 1, 2, 4, 4] {
+            chunk.write_op(OpCode::Return, line);
+        }
+
+        assert_eq!(chunk.lines.len(), 3);
+        let lines: Vec<u64> = (0..chunk.code.len()).map(|i| chunk.line(i)).collect();
+        assert_eq!(lines, [1, 1, 1, 2, 4, 4]);
+    }
+}
@@ -0,0 +1,1059 @@
+//! A single-pass compiler: it parses the `rlox` scanner's tokens with a Pratt
+//! parser and emits bytecode as it goes, without building a syntax tree.
+//!
+//! Error messages match the tree-walking interpreter's parser and resolver
+//! word for word, so both engines report the same problems the same way.
+
+use std::{collections::HashMap, mem::discriminant};
+
+use rlox::{LoxError, Scanner, Span, SyntaxError, Token, TokenType};
+
+use crate::{
+    chunk::{Chunk, OpCode},
+    memory::Heap,
+    object::{ObjFunction, ObjKind, ObjRef},
+    value::Value,
+};
+
+/// Compiles `source` into the function for its top-level code, allocating
+/// it and every object it refers to in `heap`. Collections while compiling
+/// keep `globals` alive, since they are the VM's only roots between runs.
+pub(crate) fn compile(
+    source: &str,
+    heap: &mut Heap<'_>,
+    globals: &HashMap<ObjRef, Value>,
+) -> Result<ObjRef, LoxError> {
+    let mut scanner = Scanner::new(source);
+    let (tokens, scan_errors) = scanner.scan_tokens_and_errors();
+
+    let mut compiler = Compiler::new(tokens.to_vec(), heap, globals);
+    while !compiler.is_at_end() {
+        compiler.declaration();
+    }
+    let (script, _) = compiler.end_function();
+
+    if let Some(error) = LoxError::syntax(scan_errors, compiler.errors) {
+        Err(error)
+    } else if !compiler.scope_errors.is_empty() {
+        Err(LoxError::Resolve(compiler.scope_errors))
+    } else {
+        Ok(script)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    /// The next tighter-binding level, for the right operand of a
+    /// left-associative operator.
+    fn next(self) -> Self {
+        match self {
+            Precedence::None => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+/// Compiles the expression that starts (for a prefix rule) or continues (for
+/// an infix rule) with the token just consumed. The flag says whether the
+/// expression may be the target of an assignment.
+type ParseFn<'h, 'a> = fn(&mut Compiler<'h, 'a>, bool);
+
+struct ParseRule<'h, 'a> {
+    prefix: Option<ParseFn<'h, 'a>>,
+    infix: Option<ParseFn<'h, 'a>>,
+    precedence: Precedence,
+}
+
+impl<'h, 'a> ParseRule<'h, 'a> {
+    fn new(
+        prefix: Option<ParseFn<'h, 'a>>,
+        infix: Option<ParseFn<'h, 'a>>,
+        precedence: Precedence,
+    ) -> Self {
+        Self {
+            prefix,
+            infix,
+            precedence,
+        }
+    }
+}
+
+fn rule<'h, 'a>(token_type: &TokenType) -> ParseRule<'h, 'a> {
+    match token_type {
+        TokenType::LeftParen => ParseRule::new(
+            Some(Compiler::grouping),
+            Some(Compiler::call),
+            Precedence::Call,
+        ),
+        TokenType::Minus => ParseRule::new(
+            Some(Compiler::unary),
+            Some(Compiler::binary),
+            Precedence::Term,
+        ),
+        TokenType::Plus => ParseRule::new(None, Some(Compiler::binary), Precedence::Term),
+        TokenType::Slash | TokenType::Star => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Factor)
+        }
+        TokenType::Bang => ParseRule::new(Some(Compiler::unary), None, Precedence::None),
+        TokenType::BangEqual | TokenType::EqualEqual => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Equality)
+        }
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Comparison)
+        }
+        TokenType::Identifier(_) => {
+            ParseRule::new(Some(Compiler::variable), None, Precedence::None)
+        }
+        TokenType::String(_) => ParseRule::new(Some(Compiler::string), None, Precedence::None),
+        TokenType::Number(_) => ParseRule::new(Some(Compiler::number), None, Precedence::None),
+        TokenType::And => ParseRule::new(None, Some(Compiler::and), Precedence::And),
+        TokenType::Or => ParseRule::new(None, Some(Compiler::or), Precedence::Or),
+        TokenType::False | TokenType::True | TokenType::Nil => {
+            ParseRule::new(Some(Compiler::literal), None, Precedence::None)
+        }
+        _ => ParseRule::new(None, None, Precedence::None),
+    }
+}
+
+struct Local {
+    name: String,
+    /// Scope depth the variable was declared at, or `None` while its
+    /// initializer is still being compiled.
+    depth: Option<usize>,
+    /// Whether a closure captures the variable, which then has to be moved
+    /// off the stack when it goes out of scope.
+    is_captured: bool,
+}
+
+/// Where a closure finds one of its captured variables when it is created.
+#[derive(Clone, Copy, PartialEq)]
+struct Upvalue {
+    /// A local slot of the enclosing function if `is_local`, otherwise an
+    /// index into the enclosing function's own upvalues.
+    index: u8,
+    is_local: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    Function,
+    Script,
+}
+
+/// A loop being compiled, for `break` and `continue` inside it.
+struct Loop {
+    /// Where `continue` jumps to: the condition, or a `for` loop's increment.
+    start: usize,
+    scope_depth: usize,
+    /// `break` jumps to patch once the end of the loop is known.
+    breaks: Vec<usize>,
+}
+
+/// A function whose body is being compiled.
+struct FunctionState {
+    kind: FunctionType,
+    name: Option<ObjRef>,
+    arity: usize,
+    chunk: Chunk,
+    /// Locals in scope, in stack slot order. Slot 0 holds the function being
+    /// called and has no name a program can refer to.
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(kind: FunctionType, name: Option<ObjRef>) -> Self {
+        Self {
+            kind,
+            name,
+            arity: 0,
+            chunk: Chunk::new(),
+            locals: vec![Local {
+                name: String::new(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+        }
+    }
+}
+
+struct Compiler<'h, 'a> {
+    tokens: Vec<Token>,
+    current: usize,
+    heap: &'h mut Heap<'a>,
+    globals: &'h HashMap<ObjRef, Value>,
+    /// The functions being compiled: the script first, then each function
+    /// declared inside the one before it.
+    functions: Vec<FunctionState>,
+    errors: Vec<SyntaxError>,
+    /// Errors that rlox's resolver finds rather than its parser. Like the
+    /// resolver's, they are only reported if the program parses.
+    scope_errors: Vec<SyntaxError>,
+    /// Set after an error until the next statement boundary, so that one
+    /// mistake isn't reported again as a cascade of others.
+    panic_mode: bool,
+}
+
+impl<'h, 'a> Compiler<'h, 'a> {
+    fn new(
+        tokens: Vec<Token>,
+        heap: &'h mut Heap<'a>,
+        globals: &'h HashMap<ObjRef, Value>,
+    ) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            heap,
+            globals,
+            functions: vec![FunctionState::new(FunctionType::Script, None)],
+            errors: Vec::new(),
+            scope_errors: Vec::new(),
+            panic_mode: false,
+        }
+    }
+
+    /// The innermost function being compiled.
+    fn function_state(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("the script is compiled until the end")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.function_state().chunk
+    }
+
+    fn declaration(&mut self) {
+        if self.match_token(&TokenType::Fun) {
+            self.fun_declaration();
+        } else if self.match_token(&TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+
+    fn fun_declaration(&mut self) {
+        let global = self.parse_variable("Expect function name.");
+        // A function can refer to itself, so its name is usable in its body.
+        self.mark_initialized();
+        self.function(FunctionType::Function);
+        self.define_variable(global);
+    }
+
+    /// Compiles a function's parameters and body, then emits the code that
+    /// wraps it in a closure at runtime.
+    fn function(&mut self, kind: FunctionType) {
+        let name = self.previous().lexeme.clone();
+        let name = self.intern(&name);
+        self.functions.push(FunctionState::new(kind, Some(name)));
+        self.begin_scope();
+
+        self.consume(&TokenType::LeftParen, "Expect '(' after function name.");
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                self.function_state().arity += 1;
+                if self.function_state().arity > 255 {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+                let constant = self.parse_variable("Expect parameter name.");
+                self.define_variable(constant);
+
+                if !self.match_token(&TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "Expect ')' after parameters.");
+        self.consume(&TokenType::LeftBrace, "Expect '{' before function body.");
+        // Without a proper header, the tokens after it are unlikely to be the
+        // body; leave them for `synchronize` rather than report them too.
+        if !self.panic_mode {
+            self.block();
+        }
+
+        let (function, upvalues) = self.end_function();
+        let constant = self.make_constant(Value::Obj(function));
+        self.emit_op(OpCode::Closure);
+        self.emit_byte(constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    /// Finishes the innermost function, returning it along with where its
+    /// closures find their upvalues.
+    fn end_function(&mut self) -> (ObjRef, Vec<Upvalue>) {
+        self.emit_return();
+        let state = self
+            .functions
+            .pop()
+            .expect("the script is compiled until the end");
+
+        let function = self.allocate(ObjKind::Function(ObjFunction {
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: state.chunk,
+            name: state.name,
+        }));
+        (function, state.upvalues)
+    }
+
+    fn var_declaration(&mut self) {
+        let global = self.parse_variable("Expect variable name.");
+
+        if self.match_token(&TokenType::Equal) {
+            self.expression();
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+
+        self.define_variable(global);
+    }
+
+    fn statement(&mut self) {
+        if self.match_token(&TokenType::Print) {
+            self.print_statement();
+        } else if self.match_token(&TokenType::LeftBrace) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else if self.match_token(&TokenType::If) {
+            self.if_statement();
+        } else if self.match_token(&TokenType::Return) {
+            self.return_statement();
+        } else if self.match_token(&TokenType::While) {
+            self.while_statement();
+        } else if self.match_token(&TokenType::For) {
+            self.for_statement();
+        } else if self.match_token(&TokenType::Break) {
+            self.break_statement();
+        } else if self.match_token(&TokenType::Continue) {
+            self.continue_statement();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+        self.consume(&TokenType::Semicolon, "Expect ';' after value.");
+        self.emit_op(OpCode::Print);
+    }
+
+    fn block(&mut self) {
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            self.declaration();
+        }
+        self.consume(&TokenType::RightBrace, "Expect '}' after block.");
+    }
+
+    fn if_statement(&mut self) {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.");
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        if self.match_token(&TokenType::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn return_statement(&mut self) {
+        let keyword = self.current - 1;
+        // The resolver reports this before anything in the returned value.
+        let first_error = self.scope_errors.len();
+        if self.match_token(&TokenType::Semicolon) {
+            self.emit_return();
+        } else {
+            self.expression();
+            self.consume(&TokenType::Semicolon, "Expect ';' after return value.");
+            self.emit_op(OpCode::Return);
+        }
+
+        // Reported once the whole statement is parsed, so that it can be
+        // underlined.
+        if self.function_state().kind == FunctionType::Script {
+            let mut error =
+                SyntaxError::at_token(&self.tokens[keyword], "Can't return from top-level code.");
+            error.span = Span::new(error.span.start, self.previous().span.end);
+            self.scope_errors.insert(first_error, error);
+        }
+    }
+
+    fn while_statement(&mut self) {
+        let loop_start = self.chunk().code.len();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.");
+        self.expression();
+        self.consume(&TokenType::RightParen, "Expect ')' after while condition.");
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        let breaks = self.loop_body(loop_start);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+        breaks.into_iter().for_each(|jump| self.patch_jump(jump));
+    }
+
+    /// Compiles `for` straight to jumps. The increment comes before the body
+    /// in the code, so the body loops back to it rather than to the
+    /// condition, and so does `continue`.
+    fn for_statement(&mut self) {
+        self.begin_scope();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.");
+        if self.match_token(&TokenType::Semicolon) {
+            // No initializer.
+        } else if self.match_token(&TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+
+        let mut loop_start = self.chunk().code.len();
+        let mut exit_jump = None;
+        if !self.match_token(&TokenType::Semicolon) {
+            self.expression();
+            self.consume(&TokenType::Semicolon, "Expect ';' after loop condition.");
+
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_op(OpCode::Pop);
+        }
+
+        if !self.match_token(&TokenType::RightParen) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.chunk().code.len();
+            self.expression();
+            self.emit_op(OpCode::Pop);
+            self.consume(&TokenType::RightParen, "Expect ')' after for clauses.");
+
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+
+        let breaks = self.loop_body(loop_start);
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_op(OpCode::Pop);
+        }
+        breaks.into_iter().for_each(|jump| self.patch_jump(jump));
+        self.end_scope();
+    }
+
+    /// Compiles a loop's body and returns the `break` jumps in it, which the
+    /// caller patches to land after the loop.
+    fn loop_body(&mut self, start: usize) -> Vec<usize> {
+        let state = self.function_state();
+        state.loops.push(Loop {
+            start,
+            scope_depth: state.scope_depth,
+            breaks: Vec::new(),
+        });
+        self.statement();
+        self.function_state()
+            .loops
+            .pop()
+            .map_or_else(Vec::new, |innermost| innermost.breaks)
+    }
+
+    fn break_statement(&mut self) {
+        if self.enclosing_loop("break").is_some() {
+            self.discard_loop_locals();
+            let jump = self.emit_jump(OpCode::Jump);
+            if let Some(innermost) = self.function_state().loops.last_mut() {
+                innermost.breaks.push(jump);
+            }
+        }
+        self.consume(&TokenType::Semicolon, "Expect ';' after 'break'.");
+    }
+
+    fn continue_statement(&mut self) {
+        if let Some(start) = self.enclosing_loop("continue") {
+            self.discard_loop_locals();
+            self.emit_loop(start);
+        }
+        self.consume(&TokenType::Semicolon, "Expect ';' after 'continue'.");
+    }
+
+    /// Start of the innermost loop, reporting an error if there isn't one.
+    fn enclosing_loop(&mut self, keyword: &str) -> Option<usize> {
+        let start = self
+            .function_state()
+            .loops
+            .last()
+            .map(|innermost| innermost.start);
+        if start.is_none() {
+            self.error(&format!("Can't use '{keyword}' outside of a loop."));
+        }
+        start
+    }
+
+    /// Discards the locals declared inside the innermost loop's body, before
+    /// jumping out of it. They stay declared for the rest of the body.
+    fn discard_loop_locals(&mut self) {
+        let state = self.function_state();
+        let Some(innermost) = state.loops.last() else {
+            return;
+        };
+        let captured: Vec<bool> = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| {
+                local
+                    .depth
+                    .is_none_or(|depth| depth > innermost.scope_depth)
+            })
+            .map(|local| local.is_captured)
+            .collect();
+        for is_captured in captured {
+            self.discard_local(is_captured);
+        }
+    }
+
+    /// Pops a local off the stack, first moving it into its upvalue if a
+    /// closure captured it.
+    fn discard_local(&mut self, is_captured: bool) {
+        if is_captured {
+            self.emit_op(OpCode::CloseUpvalue);
+        } else {
+            self.emit_op(OpCode::Pop);
+        }
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(&TokenType::Semicolon, "Expect ';' after expression.");
+        self.emit_op(OpCode::Pop);
+    }
+
+    fn expression(&mut self) {
+        self.parse_precedence(Precedence::Assignment);
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        // At the end of input there is nothing to advance past, so the
+        // token to blame is the current one rather than the previous.
+        let token = self.current;
+        self.advance();
+        let Some(prefix) = rule(&self.tokens[token].token_type).prefix else {
+            self.error_at(token, "Expect expression.");
+            return;
+        };
+
+        let can_assign = precedence <= Precedence::Assignment;
+        prefix(self, can_assign);
+
+        while precedence <= rule(&self.peek().token_type).precedence {
+            self.advance();
+            if let Some(infix) = rule(&self.previous().token_type).infix {
+                infix(self, can_assign);
+            }
+        }
+
+        if can_assign && self.match_token(&TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+    }
+
+    fn grouping(&mut self, _can_assign: bool) {
+        self.expression();
+        self.consume(&TokenType::RightParen, "Expect ')' after expression.");
+    }
+
+    fn call(&mut self, _can_assign: bool) {
+        let argument_count = self.argument_list();
+        self.emit_op(OpCode::Call);
+        self.emit_byte(argument_count);
+    }
+
+    fn argument_list(&mut self) -> u8 {
+        let mut count: u8 = 0;
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if count == u8::MAX {
+                    self.error_at_current("Can't have more than 255 arguments.");
+                }
+                self.expression();
+                count = count.saturating_add(1);
+
+                if !self.match_token(&TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "Expect ')' after arguments.");
+        count
+    }
+
+    fn unary(&mut self, _can_assign: bool) {
+        let operator = self.previous().token_type.clone();
+        self.parse_precedence(Precedence::Unary);
+
+        match operator {
+            TokenType::Minus => self.emit_op(OpCode::Negate),
+            TokenType::Bang => self.emit_op(OpCode::Not),
+            _ => unreachable!("no other unary operators have a prefix rule"),
+        }
+    }
+
+    fn binary(&mut self, _can_assign: bool) {
+        let operator = self.previous().token_type.clone();
+        self.parse_precedence(rule(&operator).precedence.next());
+
+        match operator {
+            TokenType::BangEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+            TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+            TokenType::Greater => self.emit_op(OpCode::Greater),
+            TokenType::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+            TokenType::Less => self.emit_op(OpCode::Less),
+            TokenType::LessEqual => self.emit_op(OpCode::LessEqual),
+            TokenType::Plus => self.emit_op(OpCode::Add),
+            TokenType::Minus => self.emit_op(OpCode::Subtract),
+            TokenType::Star => self.emit_op(OpCode::Multiply),
+            TokenType::Slash => self.emit_op(OpCode::Divide),
+            _ => unreachable!("no other operators have a binary infix rule"),
+        }
+    }
+
+    fn and(&mut self, _can_assign: bool) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end_jump);
+    }
+
+    fn or(&mut self, _can_assign: bool) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(else_jump);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end_jump);
+    }
+
+    fn literal(&mut self, _can_assign: bool) {
+        match self.previous().token_type {
+            TokenType::False => self.emit_op(OpCode::False),
+            TokenType::True => self.emit_op(OpCode::True),
+            TokenType::Nil => self.emit_op(OpCode::Nil),
+            _ => unreachable!("no other tokens have a literal rule"),
+        }
+    }
+
+    fn number(&mut self, _can_assign: bool) {
+        let TokenType::Number(value) = self.previous().token_type else {
+            unreachable!("only number tokens have a number rule");
+        };
+        self.emit_constant(Value::Number(value));
+    }
+
+    fn string(&mut self, _can_assign: bool) {
+        let TokenType::String(chars) = &self.previous().token_type else {
+            unreachable!("only string tokens have a string rule");
+        };
+        let string = self.intern(&chars.clone());
+        self.emit_constant(Value::Obj(string));
+    }
+
+    fn variable(&mut self, can_assign: bool) {
+        let name = self.previous().clone();
+
+        let innermost = self.functions.len() - 1;
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(innermost, &name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(innermost, &name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            (
+                OpCode::GetGlobal,
+                OpCode::SetGlobal,
+                self.identifier_constant(&name),
+            )
+        };
+
+        if can_assign && self.match_token(&TokenType::Equal) {
+            self.expression();
+            self.emit_op(set);
+        } else {
+            self.emit_op(get);
+        }
+        self.emit_byte(operand);
+    }
+
+    /// Consumes a variable name and declares it, returning the constant
+    /// index of its name if it is a global.
+    fn parse_variable(&mut self, message: &str) -> u8 {
+        self.consume(&TokenType::Identifier(String::new()), message);
+        let name = self.previous().clone();
+
+        if self.function_state().scope_depth > 0 {
+            self.declare_local(name);
+            return 0;
+        }
+        self.identifier_constant(&name)
+    }
+
+    fn define_variable(&mut self, global: u8) {
+        if self.function_state().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_byte(global);
+    }
+
+    /// Lets code refer to the local declared last. Globals are looked up
+    /// when they are used, so there is nothing to do for them.
+    fn mark_initialized(&mut self) {
+        let state = self.function_state();
+        if state.scope_depth == 0 {
+            return;
+        }
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(state.scope_depth);
+        }
+    }
+
+    fn declare_local(&mut self, name: Token) {
+        let state = self.function_state();
+        let already_declared = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth >= state.scope_depth))
+            .any(|local| local.name == name.lexeme);
+        if already_declared {
+            self.scope_error("Already a variable with this name in this scope.");
+        }
+
+        if self.function_state().locals.len() > u8::MAX as usize {
+            self.error("Too many local variables in function.");
+            return;
+        }
+        self.function_state().locals.push(Local {
+            name: name.lexeme,
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    /// Finds `name` among the locals of `self.functions[function]`.
+    fn resolve_local(&mut self, function: usize, name: &Token) -> Option<u8> {
+        let (slot, local) = self.functions[function]
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name.lexeme)?;
+
+        if local.depth.is_none() {
+            self.scope_error("Can't read local variable in its own initializer.");
+        }
+        Some(slot as u8)
+    }
+
+    /// Finds `name` among the locals of the functions that
+    /// `self.functions[function]` is nested in, and adds an upvalue for it to
+    /// every function in between.
+    fn resolve_upvalue(&mut self, function: usize, name: &Token) -> Option<u8> {
+        let enclosing = function.checked_sub(1)?;
+
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.functions[enclosing].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(function, slot, true));
+        }
+
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(function, index, false))
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u8, is_local: bool) -> u8 {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|&other| other == upvalue) {
+            return existing as u8;
+        }
+
+        if upvalues.len() > u8::MAX as usize {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    /// Index of the constant holding `name`, reusing an existing one so that
+    /// every mention of a global doesn't cost a constant.
+    fn identifier_constant(&mut self, name: &Token) -> u8 {
+        let name = Value::Obj(self.intern(&name.lexeme));
+        match self.chunk().constants.iter().position(|&c| c == name) {
+            Some(index) => index as u8,
+            None => self.make_constant(name),
+        }
+    }
+
+    fn allocate(&mut self, kind: ObjKind) -> ObjRef {
+        let (heap, mark_roots) = self.heap_and_roots();
+        heap.allocate(kind, mark_roots)
+    }
+
+    fn intern(&mut self, chars: &str) -> ObjRef {
+        let (heap, mark_roots) = self.heap_and_roots();
+        heap.intern(chars, mark_roots)
+    }
+
+    /// The heap, and a function marking the globals and everything the
+    /// functions being compiled refer to, for the heap to call when it
+    /// collects.
+    fn heap_and_roots(&mut self) -> (&mut Heap<'a>, impl FnOnce(&mut Heap<'a>) + '_) {
+        let Self {
+            heap,
+            globals,
+            functions,
+            ..
+        } = self;
+
+        let mark_roots = |heap: &mut Heap<'a>| {
+            for (&name, &value) in globals.iter() {
+                heap.mark_object(name);
+                heap.mark_value(value);
+            }
+            for function in functions.iter() {
+                if let Some(name) = function.name {
+                    heap.mark_object(name);
+                }
+                for &constant in &function.chunk.constants {
+                    heap.mark_value(constant);
+                }
+            }
+        };
+        (heap, mark_roots)
+    }
+
+    fn begin_scope(&mut self) {
+        self.function_state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.function_state();
+        state.scope_depth -= 1;
+
+        let mut discarded = Vec::new();
+        while let Some(local) = state
+            .locals
+            .pop_if(|local| local.depth.is_none_or(|depth| depth > state.scope_depth))
+        {
+            discarded.push(local.is_captured);
+        }
+        for is_captured in discarded {
+            self.discard_local(is_captured);
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.previous().line;
+        self.chunk().write(byte, line);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_ops(&mut self, first: OpCode, second: OpCode) {
+        self.emit_op(first);
+        self.emit_op(second);
+    }
+
+    /// Returns `nil` from the current function.
+    fn emit_return(&mut self) {
+        self.emit_ops(OpCode::Nil, OpCode::Return);
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_op(OpCode::Constant);
+        self.emit_byte(constant);
+    }
+
+    fn make_constant(&mut self, value: Value) -> u8 {
+        let constant = self.chunk().add_constant(value);
+        u8::try_from(constant).unwrap_or_else(|_| {
+            self.error("Too many constants in one chunk.");
+            0
+        })
+    }
+
+    /// Emits a jump with a placeholder offset and returns where the offset
+    /// is, for [`Compiler::patch_jump`].
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.chunk().code.len() - 2
+    }
+
+    /// Points the jump whose offset is at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk().code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.error("Too much code to jump over.");
+            return;
+        };
+        self.chunk().code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+
+        let offset = self.chunk().code.len() - loop_start + 2;
+        let offset = u16::try_from(offset).unwrap_or_else(|_| {
+            self.error("Loop body too large.");
+            0
+        });
+        let [high, low] = offset.to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    fn consume(&mut self, token_type: &TokenType, message: &str) {
+        if self.check(token_type) {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    fn match_token(&mut self, token_type: &TokenType) -> bool {
+        if !self.check(token_type) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    /// Whether the next token has the same kind as `token_type`, ignoring
+    /// any value it carries.
+    fn check(&self, token_type: &TokenType) -> bool {
+        !self.is_at_end() && discriminant(&self.peek().token_type) == discriminant(token_type)
+    }
+
+    fn advance(&mut self) {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::Eof
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+
+    /// Reports an error at the token just consumed.
+    fn error(&mut self, message: &str) {
+        self.error_at(self.current.saturating_sub(1), message);
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current, message);
+    }
+
+    fn error_at(&mut self, token: usize, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.errors
+            .push(SyntaxError::at_token(&self.tokens[token], message));
+    }
+
+    /// Reports an error at the previous token that doesn't stop the code
+    /// from parsing, so it doesn't put the compiler in panic mode.
+    fn scope_error(&mut self, message: &str) {
+        let error = SyntaxError::at_token(self.previous(), message);
+        self.scope_errors.push(error);
+    }
+
+    /// Skips tokens until what looks like the start of a new statement.
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue => return,
+                _ => (),
+            }
+
+            self.advance();
+        }
+    }
+}
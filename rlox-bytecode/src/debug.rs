@@ -0,0 +1,273 @@
+use std::fmt::Write;
+
+use crate::{
+    chunk::{Chunk, OpCode},
+    value::Value,
+};
+
+/// Lists every instruction in `chunk` in the format used by clox, under a
+/// `== name ==` header.
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+    let mut listing = format!("== {name} ==\n");
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (instruction, next) = disassemble_instruction(chunk, offset);
+        listing.push_str(&instruction);
+        listing.push('\n');
+        offset = next;
+    }
+
+    listing
+}
+
+/// Describes the instruction at `offset` and returns it along with the offset
+/// of the instruction after it. An instruction cut short by the end of the
+/// chunk is listed as truncated, and ends the chunk.
+///
+/// # Panics
+///
+/// If `offset` is past the end of the chunk.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let mut text = format!("{offset:04} ");
+    if offset > 0 && chunk.line(offset) == chunk.line(offset - 1) {
+        text.push_str("   | ");
+    } else {
+        write!(text, "{:4} ", chunk.line(offset)).unwrap();
+    }
+
+    let Ok(op) = OpCode::try_from(chunk.code[offset]) else {
+        write!(text, "Unknown opcode {}", chunk.code[offset]).unwrap();
+        return (text, offset + 1);
+    };
+
+    let next = match op {
+        OpCode::Constant => constant_instruction("OP_CONSTANT", chunk, offset, &mut text),
+        OpCode::Nil => simple_instruction("OP_NIL", offset, &mut text),
+        OpCode::True => simple_instruction("OP_TRUE", offset, &mut text),
+        OpCode::False => simple_instruction("OP_FALSE", offset, &mut text),
+        OpCode::Pop => simple_instruction("OP_POP", offset, &mut text),
+        OpCode::GetLocal => byte_instruction("OP_GET_LOCAL", chunk, offset, &mut text),
+        OpCode::SetLocal => byte_instruction("OP_SET_LOCAL", chunk, offset, &mut text),
+        OpCode::GetGlobal => constant_instruction("OP_GET_GLOBAL", chunk, offset, &mut text),
+        OpCode::DefineGlobal => constant_instruction("OP_DEFINE_GLOBAL", chunk, offset, &mut text),
+        OpCode::SetGlobal => constant_instruction("OP_SET_GLOBAL", chunk, offset, &mut text),
+        OpCode::GetUpvalue => byte_instruction("OP_GET_UPVALUE", chunk, offset, &mut text),
+        OpCode::SetUpvalue => byte_instruction("OP_SET_UPVALUE", chunk, offset, &mut text),
+        OpCode::Equal => simple_instruction("OP_EQUAL", offset, &mut text),
+        OpCode::Greater => simple_instruction("OP_GREATER", offset, &mut text),
+        OpCode::GreaterEqual => simple_instruction("OP_GREATER_EQUAL", offset, &mut text),
+        OpCode::Less => simple_instruction("OP_LESS", offset, &mut text),
+        OpCode::LessEqual => simple_instruction("OP_LESS_EQUAL", offset, &mut text),
+        OpCode::Add => simple_instruction("OP_ADD", offset, &mut text),
+        OpCode::Subtract => simple_instruction("OP_SUBTRACT", offset, &mut text),
+        OpCode::Multiply => simple_instruction("OP_MULTIPLY", offset, &mut text),
+        OpCode::Divide => simple_instruction("OP_DIVIDE", offset, &mut text),
+        OpCode::Not => simple_instruction("OP_NOT", offset, &mut text),
+        OpCode::Negate => simple_instruction("OP_NEGATE", offset, &mut text),
+        OpCode::Print => simple_instruction("OP_PRINT", offset, &mut text),
+        OpCode::Jump => jump_instruction("OP_JUMP", 1, chunk, offset, &mut text),
+        OpCode::JumpIfFalse => jump_instruction("OP_JUMP_IF_FALSE", 1, chunk, offset, &mut text),
+        OpCode::Loop => jump_instruction("OP_LOOP", -1, chunk, offset, &mut text),
+        OpCode::Call => byte_instruction("OP_CALL", chunk, offset, &mut text),
+        OpCode::Closure => closure_instruction(chunk, offset, &mut text),
+        OpCode::CloseUpvalue => simple_instruction("OP_CLOSE_UPVALUE", offset, &mut text),
+        OpCode::Return => simple_instruction("OP_RETURN", offset, &mut text),
+    };
+
+    (text, next)
+}
+
+fn simple_instruction(name: &str, offset: usize, text: &mut String) -> usize {
+    text.push_str(name);
+    offset + 1
+}
+
+fn constant_instruction(name: &str, chunk: &Chunk, offset: usize, text: &mut String) -> usize {
+    let Some(&constant) = chunk.code.get(offset + 1) else {
+        return truncated(name, chunk, text);
+    };
+    match chunk.constants.get(constant as usize) {
+        Some(value) => write!(text, "{name:<16} {constant:4} '{value}'"),
+        None => write!(text, "{name:<16} {constant:4} <no such constant>"),
+    }
+    .unwrap();
+    offset + 2
+}
+
+fn closure_instruction(chunk: &Chunk, offset: usize, text: &mut String) -> usize {
+    let Some(&constant) = chunk.code.get(offset + 1) else {
+        return truncated("OP_CLOSURE", chunk, text);
+    };
+    let mut offset = constant_instruction("OP_CLOSURE", chunk, offset, text);
+
+    let upvalue_count = match chunk.constants.get(constant as usize) {
+        Some(Value::Obj(obj)) => obj
+            .as_function()
+            .map_or(0, |function| function.upvalue_count),
+        _ => 0,
+    };
+    for _ in 0..upvalue_count {
+        let Some(&[is_local, index]) = chunk.code.get(offset..offset + 2) else {
+            write!(text, "\n{offset:04}    |                     <truncated>").unwrap();
+            return chunk.code.len();
+        };
+        let kind = if is_local == 1 { "local" } else { "upvalue" };
+        write!(
+            text,
+            "\n{offset:04}    |                     {kind} {index}"
+        )
+        .unwrap();
+        offset += 2;
+    }
+
+    offset
+}
+
+fn byte_instruction(name: &str, chunk: &Chunk, offset: usize, text: &mut String) -> usize {
+    let Some(&slot) = chunk.code.get(offset + 1) else {
+        return truncated(name, chunk, text);
+    };
+    write!(text, "{name:<16} {slot:4}").unwrap();
+    offset + 2
+}
+
+fn jump_instruction(
+    name: &str,
+    sign: isize,
+    chunk: &Chunk,
+    offset: usize,
+    text: &mut String,
+) -> usize {
+    let Some(&[high, low]) = chunk.code.get(offset + 1..offset + 3) else {
+        return truncated(name, chunk, text);
+    };
+    let jump = u16::from_be_bytes([high, low]);
+    let target = (offset + 3).wrapping_add_signed(sign * jump as isize);
+    write!(text, "{name:<16} {offset:4} -> {target}").unwrap();
+    offset + 3
+}
+
+/// Lists an instruction whose operands run past the end of the chunk.
+fn truncated(name: &str, chunk: &Chunk, text: &mut String) -> usize {
+    write!(text, "{name:<16} <truncated>").unwrap();
+    chunk.code.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::Heap,
+        object::{ObjFunction, ObjKind},
+    };
+
+    #[test]
+    fn listing_matches_clox() {
+        let mut chunk = Chunk::new();
+        let constant = chunk.add_constant(Value::Number(1.2));
+        chunk.write_op(OpCode::Constant, 123);
+        chunk.write(constant as u8, 123);
+        chunk.write_op(OpCode::Negate, 123);
+        chunk.write_op(OpCode::Return, 124);
+
+        assert_eq!(
+            disassemble_chunk(&chunk, "test chunk"),
+            "== test chunk ==\n\
+             0000  123 OP_CONSTANT         0 '1.2'\n\
+             0002    | OP_NEGATE\n\
+             0003  124 OP_RETURN\n"
+        );
+    }
+
+    #[test]
+    fn jumps_show_their_target() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::JumpIfFalse, 1);
+        chunk.write(0, 1);
+        chunk.write(1, 1);
+        chunk.write_op(OpCode::Pop, 1);
+        chunk.write_op(OpCode::Loop, 2);
+        chunk.write(0, 2);
+        chunk.write(7, 2);
+
+        assert_eq!(
+            disassemble_chunk(&chunk, "loop"),
+            "== loop ==\n\
+             0000    1 OP_JUMP_IF_FALSE    0 -> 4\n\
+             0003    | OP_POP\n\
+             0004    2 OP_LOOP             4 -> 0\n"
+        );
+    }
+
+    #[test]
+    fn closures_list_their_upvalues() {
+        let mut heap = Heap::new();
+        let name = heap.intern("f", |_| ());
+        let function = heap.allocate(
+            ObjKind::Function(ObjFunction {
+                arity: 0,
+                upvalue_count: 2,
+                chunk: Chunk::new(),
+                name: Some(name),
+            }),
+            |_| (),
+        );
+
+        let mut chunk = Chunk::new();
+        let constant = chunk.add_constant(Value::Obj(function));
+        chunk.write_op(OpCode::Closure, 1);
+        chunk.write(constant as u8, 1);
+        chunk.write(1, 1);
+        chunk.write(3, 1);
+        chunk.write(0, 1);
+        chunk.write(0, 1);
+        chunk.write_op(OpCode::CloseUpvalue, 2);
+
+        assert_eq!(
+            disassemble_chunk(&chunk, "closure"),
+            "== closure ==\n\
+             0000    1 OP_CLOSURE          0 '<fn f>'\n\
+             0002    |                     local 3\n\
+             0004    |                     upvalue 0\n\
+             0006    2 OP_CLOSE_UPVALUE\n"
+        );
+
+        // Cut off halfway through the second upvalue.
+        chunk.code.truncate(5);
+        assert_eq!(
+            disassemble_chunk(&chunk, "closure"),
+            "== closure ==\n\
+             0000    1 OP_CLOSURE          0 '<fn f>'\n\
+             0002    |                     local 3\n\
+             0004    |                     <truncated>\n"
+        );
+    }
+
+    #[test]
+    fn truncated_instructions_are_listed_not_fatal() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::Constant, 1);
+        chunk.write(7, 1);
+        chunk.write_op(OpCode::Jump, 1);
+        chunk.write(0, 1);
+
+        assert_eq!(
+            disassemble_chunk(&chunk, "truncated"),
+            "== truncated ==\n\
+             0000    1 OP_CONSTANT         7 <no such constant>\n\
+             0002    | OP_JUMP          <truncated>\n"
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_listed_not_fatal() {
+        let mut chunk = Chunk::new();
+        chunk.write(0xff, 1);
+
+        assert_eq!(
+            disassemble_instruction(&chunk, 0),
+            ("0000    1 Unknown opcode 255".to_owned(), 1)
+        );
+    }
+}
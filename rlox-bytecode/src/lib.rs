@@ -1,14 +1,16 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! A bytecode compiler and virtual machine for Lox, following the second half
+//! of Crafting Interpreters.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+mod chunk;
+mod compiler;
+mod debug;
+mod memory;
+mod object;
+mod value;
+mod vm;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use chunk::{Chunk, OpCode};
+pub use debug::{disassemble_chunk, disassemble_instruction};
+pub use object::{Obj, ObjKind, ObjRef};
+pub use value::Value;
+pub use vm::{InterpretResult, VM};
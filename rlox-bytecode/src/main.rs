@@ -0,0 +1,57 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    process,
+};
+
+use rlox_bytecode::{InterpretResult, VM};
+
+fn run_file(mut vm: VM, path: &str) -> Result<(), Box<dyn Error + 'static>> {
+    let source = fs::read_to_string(path)?;
+
+    match vm.interpret(&source) {
+        InterpretResult::Ok => Ok(()),
+        InterpretResult::CompileError => process::exit(65),
+        InterpretResult::RuntimeError => process::exit(70),
+    }
+}
+
+fn run_prompt(mut vm: VM) -> Result<(), Box<dyn Error + 'static>> {
+    let mut reader = BufReader::new(io::stdin());
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 {
+            break Ok(());
+        }
+
+        // Errors have already been reported; the session carries on.
+        vm.interpret(&line);
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut vm = VM::new();
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--stress-gc" => vm.set_stress_gc(true),
+            "--log-gc" => vm.set_log_gc(true),
+            _ => args.push(arg),
+        }
+    }
+
+    match args.as_slice() {
+        [] => run_prompt(vm),
+        [path] => run_file(vm, path),
+        _ => {
+            eprintln!("Usage: rlox-bytecode [--stress-gc] [--log-gc] [script]");
+            process::exit(64);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! The heap and its mark-sweep garbage collector.
+//!
+//! The heap can't see the VM's stack or the compiler's chunks, so whoever
+//! allocates passes along a function that marks everything it holds, for
+//! the heap to call if it is time to collect.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Arguments,
+    io::Write,
+    rc::Rc,
+};
+
+use crate::{
+    object::{Obj, ObjKind, ObjRef, UpvalueState},
+    value::Value,
+};
+
+/// How many bytes to allocate before the first collection.
+const FIRST_GC: usize = 1024 * 1024;
+
+/// How much the heap may grow, relative to what survived a collection,
+/// before the next one.
+const GROWTH_FACTOR: usize = 2;
+
+/// Where the heap traces what it does: the diagnostics output it shares with
+/// the VM.
+pub(crate) type Log<'a> = Rc<RefCell<dyn Write + 'a>>;
+
+/// Owns every object the VM allocates.
+pub struct Heap<'a> {
+    objects: Vec<ObjRef>,
+    /// Every live string, so that equal strings share one object. Strings
+    /// are not kept alive by being in here.
+    strings: HashMap<Box<str>, ObjRef>,
+    /// Marked objects whose references haven't been marked yet.
+    gray: Vec<ObjRef>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    /// Collect before every allocation, to shake out objects that aren't
+    /// marked as roots.
+    pub(crate) stress: bool,
+    /// Trace every allocation and collection here, if anywhere.
+    pub(crate) log: Option<Log<'a>>,
+}
+
+impl Default for Heap<'_> {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            strings: HashMap::new(),
+            gray: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: FIRST_GC,
+            stress: false,
+            log: None,
+        }
+    }
+}
+
+impl Heap<'_> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates an object, collecting garbage first if it is time to.
+    /// `mark_roots` marks everything the caller holds, and what `kind` refers
+    /// to survives as well, as nothing else may refer to it yet.
+    pub fn allocate(&mut self, kind: ObjKind, mark_roots: impl FnOnce(&mut Self)) -> ObjRef {
+        if self.should_collect() {
+            self.collect(|heap| {
+                mark_roots(heap);
+                heap.mark_references(&kind);
+            });
+        }
+
+        let obj = ObjRef::new(Box::new(Obj {
+            kind,
+            is_marked: Cell::new(false),
+        }));
+        self.bytes_allocated += obj.size();
+        self.objects.push(obj);
+
+        trace(
+            &self.log,
+            format_args!("{obj:p} allocate {} for {obj:?}", obj.size()),
+        );
+        obj
+    }
+
+    /// Returns the string object holding `chars`, allocating it like
+    /// [`Heap::allocate`] if there isn't one yet.
+    pub fn intern(&mut self, chars: &str, mark_roots: impl FnOnce(&mut Self)) -> ObjRef {
+        if let Some(&string) = self.strings.get(chars) {
+            return string;
+        }
+
+        let string = self.allocate(ObjKind::String(chars.into()), mark_roots);
+        self.strings.insert(chars.into(), string);
+        string
+    }
+
+    /// Whether the next allocation should be preceded by a collection.
+    fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub(crate) fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(obj) = value {
+            self.mark_object(obj);
+        }
+    }
+
+    pub(crate) fn mark_object(&mut self, obj: ObjRef) {
+        if obj.is_marked.replace(true) {
+            return;
+        }
+
+        trace(&self.log, format_args!("{obj:p} mark {obj:?}"));
+        self.gray.push(obj);
+    }
+
+    /// Marks the objects `kind` refers to. Besides tracing gray objects, this
+    /// keeps alive what an object that isn't allocated yet will refer to.
+    fn mark_references(&mut self, kind: &ObjKind) {
+        match kind {
+            ObjKind::String(_) => (),
+            ObjKind::Function(function) => {
+                if let Some(name) = function.name {
+                    self.mark_object(name);
+                }
+                for &constant in &function.chunk.constants {
+                    self.mark_value(constant);
+                }
+            }
+            ObjKind::Closure(closure) => {
+                self.mark_object(closure.function);
+                for &upvalue in &closure.upvalues {
+                    self.mark_object(upvalue);
+                }
+            }
+            ObjKind::Upvalue(upvalue) => {
+                if let UpvalueState::Closed(value) = upvalue.state() {
+                    self.mark_value(value);
+                }
+            }
+        }
+    }
+
+    /// Frees every object that can't be reached from the roots that
+    /// `mark_roots` marks.
+    pub(crate) fn collect(&mut self, mark_roots: impl FnOnce(&mut Self)) {
+        let before = self.bytes_allocated;
+        trace(&self.log, format_args!("-- gc begin"));
+
+        mark_roots(self);
+        while let Some(obj) = self.gray.pop() {
+            trace(&self.log, format_args!("{obj:p} blacken {obj:?}"));
+            self.mark_references(&obj.kind);
+        }
+        self.strings.retain(|_, string| string.is_marked.get());
+        self.sweep();
+        self.next_gc = self.bytes_allocated * GROWTH_FACTOR;
+
+        trace(&self.log, format_args!("-- gc end"));
+        trace(
+            &self.log,
+            format_args!(
+                "   collected {} bytes (from {before} to {}) next at {}",
+                before - self.bytes_allocated,
+                self.bytes_allocated,
+                self.next_gc
+            ),
+        );
+    }
+
+    fn sweep(&mut self) {
+        let mut freed = 0;
+        self.objects.retain(|&obj| {
+            if obj.is_marked.replace(false) {
+                return true;
+            }
+
+            trace(&self.log, format_args!("{obj:p} free {obj:?}"));
+            freed += obj.size();
+            // SAFETY: nothing marked refers to the object, and only marked
+            // objects are reachable from the VM.
+            unsafe { obj.free() };
+            false
+        });
+        self.bytes_allocated -= freed;
+    }
+}
+
+/// Writes `line` to `log`, if there is one.
+fn trace(log: &Option<Log<'_>>, line: Arguments<'_>) {
+    if let Some(log) = log {
+        // Failing to trace the collector must not stop it.
+        let _ = writeln!(log.borrow_mut(), "{line}");
+    }
+}
+
+impl Drop for Heap<'_> {
+    fn drop(&mut self) {
+        for obj in self.objects.drain(..) {
+            // SAFETY: the heap is going away, taking every object with it.
+            unsafe { obj.free() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        object::{ObjClosure, ObjFunction, ObjUpvalue},
+    };
+
+    fn function(heap: &mut Heap, name: &str) -> ObjRef {
+        let name = heap.intern(name, |_| ());
+        heap.allocate(
+            ObjKind::Function(ObjFunction {
+                arity: 0,
+                upvalue_count: 1,
+                chunk: Chunk::new(),
+                name: Some(name),
+            }),
+            |_| (),
+        )
+    }
+
+    #[test]
+    fn unreachable_objects_are_freed() {
+        let mut heap = Heap::new();
+        let kept = function(&mut heap, "kept");
+        function(&mut heap, "dropped");
+        let before = heap.bytes_allocated;
+
+        heap.collect(|heap| heap.mark_object(kept));
+
+        assert_eq!(heap.objects.len(), 2);
+        assert!(heap.bytes_allocated < before);
+        assert_eq!(heap.next_gc, heap.bytes_allocated * GROWTH_FACTOR);
+        // The interned name went with its function, and interning it again
+        // makes a new string.
+        assert!(!heap.strings.contains_key("dropped"));
+        assert!(heap.strings.contains_key("kept"));
+        heap.intern("dropped", |_| ());
+        assert_eq!(heap.objects.len(), 3);
+    }
+
+    #[test]
+    fn cycles_are_freed() {
+        let mut heap = Heap::new();
+        let function = function(&mut heap, "f");
+        let upvalue = heap.allocate(ObjKind::Upvalue(ObjUpvalue::new(0)), |_| ());
+        let closure = heap.allocate(
+            ObjKind::Closure(ObjClosure {
+                function,
+                upvalues: vec![upvalue],
+            }),
+            |_| (),
+        );
+        // The closure captured a variable that holds the closure itself.
+        upvalue.as_upvalue().unwrap().close(Value::Obj(closure));
+
+        heap.collect(|heap| heap.mark_object(upvalue));
+        assert_eq!(heap.objects.len(), 4);
+
+        heap.collect(|_| ());
+        assert!(heap.objects.is_empty());
+        assert!(heap.strings.is_empty());
+        assert_eq!(heap.bytes_allocated, 0);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    cell::Cell,
+    fmt::{Debug, Display, Pointer},
+    hash::Hash,
+    ops::Deref,
+    ptr::NonNull,
+};
+
+use crate::{chunk::Chunk, value::Value};
+
+/// A heap-allocated value, owned by the [`Heap`](crate::memory::Heap) that
+/// created it.
+pub struct Obj {
+    pub kind: ObjKind,
+    /// Set while a collection finds the object reachable.
+    pub(crate) is_marked: Cell<bool>,
+}
+
+impl Obj {
+    /// Roughly how many bytes the object takes up, counting what it owns.
+    pub(crate) fn size(&self) -> usize {
+        size_of::<Obj>()
+            + match &self.kind {
+                ObjKind::String(chars) => chars.len(),
+                ObjKind::Function(function) => function.chunk.heap_size(),
+                ObjKind::Closure(closure) => closure.upvalues.capacity() * size_of::<ObjRef>(),
+                ObjKind::Upvalue(_) => 0,
+            }
+    }
+}
+
+pub enum ObjKind {
+    /// An interned string: two strings with the same contents are always the
+    /// same object, so they can be compared by reference.
+    String(Box<str>),
+    Function(ObjFunction),
+    Closure(ObjClosure),
+    Upvalue(ObjUpvalue),
+}
+
+/// A compiled function. Lox code only ever sees functions wrapped in an
+/// [`ObjClosure`].
+pub struct ObjFunction {
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    /// `None` for the top-level script.
+    pub name: Option<ObjRef>,
+}
+
+/// A function together with the variables it captured from enclosing
+/// functions.
+pub struct ObjClosure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A variable captured by a closure.
+pub struct ObjUpvalue {
+    state: Cell<UpvalueState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum UpvalueState {
+    /// The variable is still on the stack, in this slot.
+    Open(usize),
+    /// The variable's function has returned, so the upvalue holds it.
+    Closed(Value),
+}
+
+impl ObjUpvalue {
+    pub(crate) fn new(slot: usize) -> Self {
+        Self {
+            state: Cell::new(UpvalueState::Open(slot)),
+        }
+    }
+
+    pub(crate) fn state(&self) -> UpvalueState {
+        self.state.get()
+    }
+
+    pub(crate) fn close(&self, value: Value) {
+        self.state.set(UpvalueState::Closed(value));
+    }
+}
+
+impl Display for Obj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ObjKind::String(chars) => write!(f, "{chars}"),
+            ObjKind::Function(function) => match function.name {
+                Some(name) => write!(f, "<fn {name}>"),
+                None => write!(f, "<script>"),
+            },
+            ObjKind::Closure(closure) => write!(f, "{}", closure.function),
+            ObjKind::Upvalue(_) => write!(f, "upvalue"),
+        }
+    }
+}
+
+/// A reference to an [`Obj`]. References compare, and hash, by identity.
+///
+/// The heap frees an object only once no references to it are reachable from
+/// the VM, so dereferencing a reference the VM can still see is always valid.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(NonNull<Obj>);
+
+impl ObjRef {
+    pub(crate) fn new(obj: Box<Obj>) -> Self {
+        Self(NonNull::from(Box::leak(obj)))
+    }
+
+    /// Frees the object.
+    ///
+    /// # Safety
+    ///
+    /// `self` must have been created by [`ObjRef::new`], and neither it nor
+    /// any copy of it may be used afterwards.
+    pub(crate) unsafe fn free(self) {
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match &self.kind {
+            ObjKind::String(chars) => Some(chars),
+            _ => None,
+        }
+    }
+
+    pub fn as_function(&self) -> Option<&ObjFunction> {
+        match &self.kind {
+            ObjKind::Function(function) => Some(function),
+            _ => None,
+        }
+    }
+
+    pub fn as_closure(&self) -> Option<&ObjClosure> {
+        match &self.kind {
+            ObjKind::Closure(closure) => Some(closure),
+            _ => None,
+        }
+    }
+
+    pub fn as_upvalue(&self) -> Option<&ObjUpvalue> {
+        match &self.kind {
+            ObjKind::Upvalue(upvalue) => Some(upvalue),
+            _ => None,
+        }
+    }
+}
+
+impl Deref for ObjRef {
+    type Target = Obj;
+
+    fn deref(&self) -> &Obj {
+        // SAFETY: see the type's documentation.
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Display for ObjRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", **self)
+    }
+}
+
+impl Pointer for ObjRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Pointer::fmt(&self.0, f)
+    }
+}
+
+impl Debug for ObjRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ObjKind::String(chars) => write!(f, "{chars:?}"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt::Display;
+
+use crate::object::ObjRef;
+
+/// A Lox value as the virtual machine sees it. Everything that doesn't fit
+/// in a machine word lives on the heap behind an [`ObjRef`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    /// `nil` and `false` are falsey; every other value is truthy.
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Boolean(false))
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(v) => write!(f, "{v}"),
+            Value::Number(v) => rlox::write_number(f, *v),
+            Value::Obj(v) => write!(f, "{v}"),
+        }
+    }
+}
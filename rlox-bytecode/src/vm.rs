@@ -0,0 +1,713 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use rlox::{Frame, Lox};
+
+use crate::{
+    chunk::OpCode,
+    compiler::compile,
+    memory::{Heap, Log},
+    object::{ObjClosure, ObjFunction, ObjKind, ObjRef, ObjUpvalue, UpvalueState},
+    value::Value,
+};
+
+/// How deep calls can nest before the VM reports a stack overflow.
+const FRAMES_MAX: usize = 64;
+
+/// How a call to [`VM::interpret`] went. Errors have already been
+/// reported to the VM's diagnostics output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterpretResult {
+    Ok,
+    CompileError,
+    RuntimeError,
+}
+
+/// A call to a closure that hasn't returned yet.
+struct CallFrame {
+    closure: ObjRef,
+    /// Offset in the function's code of the next byte to execute.
+    ip: usize,
+    /// Index in the VM's stack of the frame's slot 0, which holds the
+    /// closure being called. Its arguments and locals follow.
+    slots: usize,
+}
+
+impl CallFrame {
+    fn closure(&self) -> &ObjClosure {
+        self.closure.as_closure().expect("only closures are called")
+    }
+
+    fn function(&self) -> &ObjFunction {
+        self.closure()
+            .function
+            .as_function()
+            .expect("closures wrap functions")
+    }
+}
+
+pub struct VM<'a> {
+    frames: Vec<CallFrame>,
+    stack: Vec<Value>,
+    globals: HashMap<ObjRef, Value>,
+    /// Upvalues that still point into `stack`, in slot order, so that the
+    /// closures capturing a variable all share one upvalue.
+    open_upvalues: Vec<ObjRef>,
+    heap: Heap<'a>,
+    output: Box<dyn Write + 'a>,
+    /// Shared with the heap, which traces collections here.
+    diagnostics: Log<'a>,
+}
+
+impl VM<'static> {
+    /// Creates a VM that prints to the process's stdout and reports errors on
+    /// its stderr.
+    pub fn new() -> Self {
+        Self::with_output(io::stdout(), io::stderr())
+    }
+}
+
+impl Default for VM<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> VM<'a> {
+    /// Creates a VM that sends everything the program `print`s to `output`
+    /// and every error to `diagnostics`.
+    pub fn with_output(output: impl Write + 'a, diagnostics: impl Write + 'a) -> Self {
+        Self {
+            frames: Vec::new(),
+            stack: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            heap: Heap::new(),
+            output: Box::new(output),
+            diagnostics: Rc::new(RefCell::new(diagnostics)),
+        }
+    }
+
+    /// Makes the garbage collector run before every allocation rather than
+    /// once enough memory has been allocated, so that a missing root shows
+    /// up at once instead of by chance.
+    pub fn set_stress_gc(&mut self, stress: bool) {
+        self.heap.stress = stress;
+    }
+
+    /// Traces every allocation, mark and free, and a summary of every
+    /// collection, to the diagnostics output.
+    pub fn set_log_gc(&mut self, log: bool) {
+        self.heap.log = log.then(|| Rc::clone(&self.diagnostics));
+    }
+
+    /// Compiles and runs `source`. Globals defined by earlier calls stay
+    /// visible to later ones, as in a REPL session.
+    pub fn interpret(&mut self, source: &str) -> InterpretResult {
+        match compile(source, &mut self.heap, &self.globals) {
+            Ok(script) => self.interpret_function(script),
+            Err(error) => {
+                // There is nowhere left to report a failure to report.
+                let _ = writeln!(
+                    self.diagnostics.borrow_mut(),
+                    "{}",
+                    Lox::report(source, &error)
+                );
+                InterpretResult::CompileError
+            }
+        }
+    }
+
+    fn interpret_function(&mut self, function: ObjRef) -> InterpretResult {
+        let script = self.allocate(ObjKind::Closure(ObjClosure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.push(Value::Obj(script));
+
+        match self.call(script, 0).and_then(|()| self.run()) {
+            Ok(()) => InterpretResult::Ok,
+            Err(message) => {
+                self.runtime_error(&message);
+                InterpretResult::RuntimeError
+            }
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        loop {
+            let op = OpCode::try_from(self.read_byte())
+                .map_err(|byte| format!("Unknown opcode {byte}."))?;
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    let Some(&value) = self.globals.get(&name) else {
+                        return Err(format!("Undefined variable '{name}'."));
+                    };
+                    self.push(value);
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    self.globals.insert(name, self.peek(0));
+                    self.pop();
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    let Some(global) = self.globals.get_mut(&name) else {
+                        return Err(format!("Undefined variable '{name}'."));
+                    };
+                    *global = value;
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = match self.upvalue(index).state() {
+                        UpvalueState::Open(slot) => self.stack[slot],
+                        UpvalueState::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = self.peek(0);
+                    let upvalue = self.upvalue(index);
+                    match upvalue.state() {
+                        UpvalueState::Open(slot) => self.stack[slot] = value,
+                        UpvalueState::Closed(_) => upvalue.close(value),
+                    }
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Boolean(a == b));
+                }
+                OpCode::Greater => self.binary_op(|a, b| Value::Boolean(a > b))?,
+                OpCode::GreaterEqual => self.binary_op(|a, b| Value::Boolean(a >= b))?,
+                OpCode::Less => self.binary_op(|a, b| Value::Boolean(a < b))?,
+                OpCode::LessEqual => self.binary_op(|a, b| Value::Boolean(a <= b))?,
+                OpCode::Add => match (self.peek(1), self.peek(0)) {
+                    (Value::Number(_), Value::Number(_)) => {
+                        self.binary_op(|a, b| Value::Number(a + b))?
+                    }
+                    (Value::Obj(a), Value::Obj(b))
+                        if a.as_string().is_some() && b.as_string().is_some() =>
+                    {
+                        let string = self.intern(&format!("{a}{b}"));
+                        self.pop();
+                        self.pop();
+                        self.push(Value::Obj(string));
+                    }
+                    _ => return Err("Operands must be two numbers or two strings.".to_owned()),
+                },
+                OpCode::Subtract => self.binary_op(|a, b| Value::Number(a - b))?,
+                OpCode::Multiply => self.binary_op(|a, b| Value::Number(a * b))?,
+                OpCode::Divide => self.binary_op(|a, b| Value::Number(a / b))?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Boolean(value.is_falsey()));
+                }
+                OpCode::Negate => {
+                    let Value::Number(value) = self.peek(0) else {
+                        return Err("Operand must be a number.".to_owned());
+                    };
+                    self.pop();
+                    self.push(Value::Number(-value));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.output, "{value}")
+                        .map_err(|err| format!("Could not write output: {err}."))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argument_count = self.read_byte() as usize;
+                    self.call_value(self.peek(argument_count), argument_count)?;
+                }
+                OpCode::Closure => {
+                    let Value::Obj(function) = self.read_constant() else {
+                        unreachable!("closures are made from function constants");
+                    };
+                    let upvalue_count = function
+                        .as_function()
+                        .expect("closures are made from function constants")
+                        .upvalue_count;
+
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.frame().closure().upvalues[index]
+                        });
+                    }
+
+                    // The upvalues captured so far are still reachable: from
+                    // the open list if they were just created, or otherwise
+                    // from the running closure.
+                    let closure =
+                        self.allocate(ObjKind::Closure(ObjClosure { function, upvalues }));
+                    self.push(Value::Obj(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self
+                        .frames
+                        .pop()
+                        .expect("a frame is running until the script returns");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+            }
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames
+            .last()
+            .expect("a frame is running until the script returns")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("a frame is running until the script returns")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        frame.ip += 1;
+        frame.function().chunk.code[frame.ip - 1]
+    }
+
+    fn read_short(&mut self) -> usize {
+        u16::from_be_bytes([self.read_byte(), self.read_byte()]) as usize
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        self.frame().function().chunk.constants[index]
+    }
+
+    fn read_string(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Obj(obj) if obj.as_string().is_some() => obj,
+            value => unreachable!("variable names are strings, got {value}"),
+        }
+    }
+
+    fn upvalue(&self, index: usize) -> &ObjUpvalue {
+        self.frame().closure().upvalues[index]
+            .as_upvalue()
+            .expect("closures capture upvalues")
+    }
+
+    fn call_value(&mut self, callee: Value, argument_count: usize) -> Result<(), String> {
+        match callee {
+            Value::Obj(obj) if obj.as_closure().is_some() => self.call(obj, argument_count),
+            _ => Err("Can only call functions and classes.".to_owned()),
+        }
+    }
+
+    /// Starts running `closure`, whose arguments are the top
+    /// `argument_count` values on the stack, just above the closure itself.
+    fn call(&mut self, closure: ObjRef, argument_count: usize) -> Result<(), String> {
+        let frame = CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - argument_count - 1,
+        };
+
+        let arity = frame.function().arity;
+        if argument_count != arity {
+            return Err(format!(
+                "Expected {arity} arguments but got {argument_count}."
+            ));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err("Stack overflow.".to_owned());
+        }
+
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    /// Returns the upvalue for the variable in stack slot `slot`, creating
+    /// it unless a closure already captured the variable.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let position = self
+            .open_upvalues
+            .partition_point(|upvalue| open_slot(upvalue) < slot);
+        if let Some(&upvalue) = self.open_upvalues.get(position)
+            && open_slot(&upvalue) == slot
+        {
+            return upvalue;
+        }
+
+        let upvalue = self.allocate(ObjKind::Upvalue(ObjUpvalue::new(slot)));
+        self.open_upvalues.insert(position, upvalue);
+        upvalue
+    }
+
+    /// Moves the variables in stack slots from `first` up into their
+    /// upvalues, before those slots are discarded.
+    fn close_upvalues(&mut self, first: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let slot = open_slot(&upvalue);
+            if slot < first {
+                break;
+            }
+            upvalue
+                .as_upvalue()
+                .expect("only upvalues are open")
+                .close(self.stack[slot]);
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn allocate(&mut self, kind: ObjKind) -> ObjRef {
+        let (heap, mark_roots) = self.heap_and_roots();
+        heap.allocate(kind, mark_roots)
+    }
+
+    fn intern(&mut self, chars: &str) -> ObjRef {
+        let (heap, mark_roots) = self.heap_and_roots();
+        heap.intern(chars, mark_roots)
+    }
+
+    /// The heap, and a function marking everything the program can still
+    /// reach, for the heap to call when it collects.
+    fn heap_and_roots(&mut self) -> (&mut Heap<'a>, impl FnOnce(&mut Heap<'a>) + '_) {
+        let Self {
+            frames,
+            stack,
+            globals,
+            open_upvalues,
+            heap,
+            ..
+        } = self;
+
+        let mark_roots = |heap: &mut Heap<'a>| {
+            for &value in stack.iter() {
+                heap.mark_value(value);
+            }
+            for frame in frames.iter() {
+                heap.mark_object(frame.closure);
+            }
+            for &upvalue in open_upvalues.iter() {
+                heap.mark_object(upvalue);
+            }
+            for (&name, &value) in globals.iter() {
+                heap.mark_object(name);
+                heap.mark_value(value);
+            }
+        };
+        (heap, mark_roots)
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("the compiler never pops an empty stack")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// Applies `op` to the two numbers on top of the stack.
+    fn binary_op(&mut self, op: fn(f64, f64) -> Value) -> Result<(), String> {
+        let (Value::Number(a), Value::Number(b)) = (self.peek(1), self.peek(0)) else {
+            return Err("Operands must be numbers.".to_owned());
+        };
+        self.pop();
+        self.pop();
+        self.push(op(a, b));
+        Ok(())
+    }
+
+    /// Reports `message` with the call stack it happened in, innermost call
+    /// first, and abandons every call.
+    fn runtime_error(&mut self, message: &str) {
+        let mut report = message.to_owned();
+        for frame in self.frames.iter().rev() {
+            let function = frame.function();
+            let frame = Frame {
+                function: function.name.map(|name| name.to_string()),
+                line: function.chunk.line(frame.ip - 1),
+            };
+            report.push_str(&format!("\n{frame}"));
+        }
+        // There is nowhere left to report a failure to report.
+        let _ = writeln!(self.diagnostics.borrow_mut(), "{report}");
+
+        self.frames.clear();
+        self.stack.clear();
+        self.open_upvalues.clear();
+    }
+}
+
+/// The stack slot of an upvalue in [`VM::open_upvalues`].
+fn open_slot(upvalue: &ObjRef) -> usize {
+    match upvalue.as_upvalue().map(ObjUpvalue::state) {
+        Some(UpvalueState::Open(slot)) => slot,
+        _ => unreachable!("upvalues are closed as they leave the open list"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    /// Runs the chunk built by `build` and returns the result along with
+    /// everything printed and reported.
+    fn run(build: impl FnOnce(&mut Chunk, &mut Heap)) -> (InterpretResult, String, String) {
+        let mut output = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        let result = {
+            let mut vm = VM::with_output(&mut output, &mut diagnostics);
+            let mut chunk = Chunk::new();
+            build(&mut chunk, &mut vm.heap);
+            // Only the compiler's output is known to be well formed, so
+            // only tests get to run hand-assembled chunks.
+            let script = vm.allocate(ObjKind::Function(ObjFunction {
+                arity: 0,
+                upvalue_count: 0,
+                chunk,
+                name: None,
+            }));
+            vm.interpret_function(script)
+        };
+
+        (
+            result,
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(diagnostics).unwrap(),
+        )
+    }
+
+    fn constant(chunk: &mut Chunk, value: Value, line: u64) {
+        let index = chunk.add_constant(value);
+        chunk.write_op(OpCode::Constant, line);
+        chunk.write(index as u8, line);
+    }
+
+    fn with_operand(chunk: &mut Chunk, op: OpCode, operand: u8, line: u64) {
+        chunk.write_op(op, line);
+        chunk.write(operand, line);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // print -((1.2 + 3.4) / 5.6);
+        let result = run(|chunk, _| {
+            constant(chunk, Value::Number(1.2), 1);
+            constant(chunk, Value::Number(3.4), 1);
+            chunk.write_op(OpCode::Add, 1);
+            constant(chunk, Value::Number(5.6), 1);
+            chunk.write_op(OpCode::Divide, 1);
+            chunk.write_op(OpCode::Negate, 1);
+            chunk.write_op(OpCode::Print, 1);
+            chunk.write_op(OpCode::Nil, 1);
+            chunk.write_op(OpCode::Return, 1);
+        });
+
+        assert_eq!(
+            result,
+            (
+                InterpretResult::Ok,
+                "-0.8214285714285714\n".into(),
+                "".into()
+            )
+        );
+    }
+
+    #[test]
+    fn comparison_and_not() {
+        // print !(1 < 2); print nil == false; print 3 > 2;
+        let result = run(|chunk, _| {
+            constant(chunk, Value::Number(1.0), 1);
+            constant(chunk, Value::Number(2.0), 1);
+            chunk.write_op(OpCode::Less, 1);
+            chunk.write_op(OpCode::Not, 1);
+            chunk.write_op(OpCode::Print, 1);
+            chunk.write_op(OpCode::Nil, 2);
+            chunk.write_op(OpCode::False, 2);
+            chunk.write_op(OpCode::Equal, 2);
+            chunk.write_op(OpCode::Print, 2);
+            constant(chunk, Value::Number(3.0), 3);
+            constant(chunk, Value::Number(2.0), 3);
+            chunk.write_op(OpCode::Greater, 3);
+            chunk.write_op(OpCode::Print, 3);
+            chunk.write_op(OpCode::Nil, 3);
+            chunk.write_op(OpCode::Return, 3);
+        });
+
+        assert_eq!(
+            result,
+            (
+                InterpretResult::Ok,
+                "false\nfalse\ntrue\n".into(),
+                "".into()
+            )
+        );
+    }
+
+    #[test]
+    fn strings_are_concatenated_and_interned() {
+        // print "a" + "b" == "ab";
+        let result = run(|chunk, heap| {
+            constant(chunk, Value::Obj(heap.intern("a", |_| ())), 1);
+            constant(chunk, Value::Obj(heap.intern("b", |_| ())), 1);
+            chunk.write_op(OpCode::Add, 1);
+            constant(chunk, Value::Obj(heap.intern("ab", |_| ())), 1);
+            chunk.write_op(OpCode::Equal, 1);
+            chunk.write_op(OpCode::Print, 1);
+            chunk.write_op(OpCode::Nil, 1);
+            chunk.write_op(OpCode::Return, 1);
+        });
+
+        assert_eq!(result, (InterpretResult::Ok, "true\n".into(), "".into()));
+    }
+
+    #[test]
+    fn globals() {
+        // var x = 1; x = x + 1; print x; print y;
+        let result = run(|chunk, heap| {
+            let x = chunk.add_constant(Value::Obj(heap.intern("x", |_| ()))) as u8;
+            let y = chunk.add_constant(Value::Obj(heap.intern("y", |_| ()))) as u8;
+            constant(chunk, Value::Number(1.0), 1);
+            with_operand(chunk, OpCode::DefineGlobal, x, 1);
+            with_operand(chunk, OpCode::GetGlobal, x, 2);
+            constant(chunk, Value::Number(1.0), 2);
+            chunk.write_op(OpCode::Add, 2);
+            with_operand(chunk, OpCode::SetGlobal, x, 2);
+            chunk.write_op(OpCode::Pop, 2);
+            with_operand(chunk, OpCode::GetGlobal, x, 3);
+            chunk.write_op(OpCode::Print, 3);
+            with_operand(chunk, OpCode::GetGlobal, y, 4);
+            chunk.write_op(OpCode::Print, 4);
+            chunk.write_op(OpCode::Nil, 4);
+            chunk.write_op(OpCode::Return, 4);
+        });
+
+        assert_eq!(
+            result,
+            (
+                InterpretResult::RuntimeError,
+                "2\n".into(),
+                "Undefined variable 'y'.\n[line 4] in script\n".into()
+            )
+        );
+    }
+
+    #[test]
+    fn locals_and_loops() {
+        // { var i = 0; while (i < 3) { i = i + 1; print i; } }
+        // Slot 0 holds the script itself.
+        let result = run(|chunk, _| {
+            constant(chunk, Value::Number(0.0), 1);
+            let loop_start = chunk.code.len();
+            with_operand(chunk, OpCode::GetLocal, 1, 1);
+            constant(chunk, Value::Number(3.0), 1);
+            chunk.write_op(OpCode::Less, 1);
+            chunk.write_op(OpCode::JumpIfFalse, 1);
+            let exit_jump = chunk.code.len();
+            chunk.write(0, 1);
+            chunk.write(0, 1);
+            chunk.write_op(OpCode::Pop, 1);
+            with_operand(chunk, OpCode::GetLocal, 1, 2);
+            constant(chunk, Value::Number(1.0), 2);
+            chunk.write_op(OpCode::Add, 2);
+            with_operand(chunk, OpCode::SetLocal, 1, 2);
+            chunk.write_op(OpCode::Pop, 2);
+            with_operand(chunk, OpCode::GetLocal, 1, 2);
+            chunk.write_op(OpCode::Print, 2);
+            chunk.write_op(OpCode::Loop, 2);
+            let back = (chunk.code.len() + 2 - loop_start) as u16;
+            chunk.write(back.to_be_bytes()[0], 2);
+            chunk.write(back.to_be_bytes()[1], 2);
+            let forward = (chunk.code.len() - exit_jump - 2) as u16;
+            chunk.code[exit_jump..exit_jump + 2].copy_from_slice(&forward.to_be_bytes());
+            chunk.write_op(OpCode::Pop, 3);
+            chunk.write_op(OpCode::Pop, 3);
+            chunk.write_op(OpCode::Nil, 3);
+            chunk.write_op(OpCode::Return, 3);
+        });
+
+        assert_eq!(result, (InterpretResult::Ok, "1\n2\n3\n".into(), "".into()));
+    }
+
+    #[test]
+    fn type_errors_are_runtime_errors() {
+        // print -"a";
+        let result = run(|chunk, heap| {
+            constant(chunk, Value::Obj(heap.intern("a", |_| ())), 7);
+            chunk.write_op(OpCode::Negate, 7);
+            chunk.write_op(OpCode::Print, 7);
+            chunk.write_op(OpCode::Nil, 7);
+            chunk.write_op(OpCode::Return, 7);
+        });
+
+        assert_eq!(
+            result,
+            (
+                InterpretResult::RuntimeError,
+                "".into(),
+                "Operand must be a number.\n[line 7] in script\n".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::io;
+
+use rlox::Lox;
+use rlox_bytecode::{InterpretResult, VM};
+
+/// Runs `source` on a fresh VM, returning what it printed and reported.
+fn run(source: &str) -> (InterpretResult, String, String) {
+    let mut output = Vec::new();
+    let mut diagnostics = Vec::new();
+    let result = VM::with_output(&mut output, &mut diagnostics).interpret(source);
+
+    (
+        result,
+        String::from_utf8(output).unwrap(),
+        String::from_utf8(diagnostics).unwrap(),
+    )
+}
+
+fn output_of(source: &str) -> String {
+    let (result, output, diagnostics) = run(source);
+    assert_eq!(result, InterpretResult::Ok, "{diagnostics}");
+    output
+}
+
+#[test]
+fn expressions_respect_precedence() {
+    assert_eq!(
+        output_of("print 1 + 2 * 3 - 4 / 2; print -(1 + 2) * 3; print !(1 < 2) == false;"),
+        "5\n-9\ntrue\n"
+    );
+    assert_eq!(
+        output_of("print 1 <= 1; print 2 >= 3; print 1 != 2; print \"a\" + \"b\";"),
+        "true\nfalse\ntrue\nab\n"
+    );
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    assert_eq!(
+        output_of("print nil or \"default\"; print false and undefined; print 1 and 2;"),
+        "default\nfalse\n2\n"
+    );
+}
+
+#[test]
+fn globals_and_locals() {
+    let source = r#"
+var a = "global";
+{
+  var a = "outer";
+  {
+    var a = "inner";
+    print a;
+  }
+  print a;
+  a = "assigned";
+  print a;
+}
+print a;
+"#;
+    assert_eq!(output_of(source), "inner\nouter\nassigned\nglobal\n");
+}
+
+#[test]
+fn control_flow() {
+    let source = r#"
+if (1 > 2) print "then"; else print "else";
+
+var i = 0;
+while (i < 3) { print i; i = i + 1; }
+
+for (var j = 0; j < 10; j = j + 1) {
+  var k = j * 2;
+  if (j == 1) continue;
+  if (j == 3) break;
+  print k;
+}
+"#;
+    assert_eq!(output_of(source), "else\n0\n1\n2\n0\n4\n");
+}
+
+#[test]
+fn globals_persist_between_calls() {
+    let mut output = Vec::new();
+    {
+        let mut vm = VM::with_output(&mut output, io::sink());
+        assert_eq!(vm.interpret("var x = 1;"), InterpretResult::Ok);
+        assert_eq!(vm.interpret("print x + 1;"), InterpretResult::Ok);
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "2\n");
+}
+
+#[test]
+fn runtime_errors_are_reported_with_their_line() {
+    assert_eq!(
+        run("print 1;\nprint -\"a\";"),
+        (
+            InterpretResult::RuntimeError,
+            "1\n".into(),
+            "Operand must be a number.\n[line 2] in script\n".into()
+        )
+    );
+}
+
+#[test]
+fn functions_and_recursion() {
+    let source = r#"
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 2) + fib(n - 1);
+}
+print fib(15);
+
+fun noReturn() {}
+print noReturn();
+print fib;
+"#;
+    assert_eq!(output_of(source), "610\nnil\n<fn fib>\n");
+}
+
+#[test]
+fn closures_capture_variables_not_values() {
+    let source = r#"
+fun makeCounter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+var counter = makeCounter();
+counter();
+print counter();
+print makeCounter()();
+
+var get;
+var set;
+{
+  var shared = "before";
+  fun g() { return shared; }
+  fun s(value) { shared = value; }
+  get = g;
+  set = s;
+  shared = "assigned";
+}
+print get();
+set("after");
+print get();
+
+fun outer() {
+  var x = "outer";
+  fun middle() {
+    fun inner() { return x; }
+    return inner;
+  }
+  return middle;
+}
+print outer()()();
+"#;
+    assert_eq!(output_of(source), "2\n1\nassigned\nafter\nouter\n");
+}
+
+#[test]
+fn loop_variables_are_closed_over_each_iteration() {
+    let source = r#"
+var first;
+var second;
+for (var i = 1; i <= 2; i = i + 1) {
+  var j = i;
+  fun f() { return j; }
+  if (i == 1) first = f; else second = f;
+  if (i == 2) break;
+}
+print first();
+print second();
+"#;
+    assert_eq!(output_of(source), "1\n2\n");
+}
+
+#[test]
+fn runtime_errors_in_functions_report_the_call_stack() {
+    let source = "fun inner() {\n  return -\"a\";\n}\nfun outer() {\n  inner();\n}\nouter();";
+    let (result, _, diagnostics) = run(source);
+    assert_eq!(result, InterpretResult::RuntimeError);
+    assert_eq!(
+        diagnostics,
+        "Operand must be a number.\n[line 2] in inner()\n[line 5] in outer()\n[line 7] in script\n"
+    );
+
+    let mut tree_walker = Vec::new();
+    let _ = Lox::with_output(io::sink(), &mut tree_walker).run(source);
+    assert_eq!(diagnostics, String::from_utf8(tree_walker).unwrap());
+}
+
+#[test]
+fn bad_calls_are_runtime_errors() {
+    assert_eq!(
+        run("fun f(a, b) {}\nf(1);").2,
+        "Expected 2 arguments but got 1.\n[line 2] in script\n"
+    );
+    assert_eq!(
+        run("var x = 1;\nx();").2,
+        "Can only call functions and classes.\n[line 2] in script\n"
+    );
+}
+
+#[test]
+fn output_matches_the_tree_walker() {
+    let sources = [
+        "print 1 <= 0/0; print 1 >= 0/0;",
+        "print 1 < 0/0; print 1 > 0/0; print 0/0 == 0/0; print 0/0 != 0/0;",
+        "print 1 <= 1; print 1 >= 2; print -0 <= 0; print 2 >= 1;",
+    ];
+
+    for source in sources {
+        let mut tree_walker = Vec::new();
+        Lox::with_output(&mut tree_walker, io::sink())
+            .run(source)
+            .unwrap();
+        assert_eq!(
+            output_of(source),
+            String::from_utf8(tree_walker).unwrap(),
+            "{source}"
+        );
+    }
+}
+
+#[test]
+fn compile_errors_match_the_tree_walker() {
+    let sources = [
+        "print ;",
+        "var = 1;",
+        "var a = 1",
+        "1 + 2 = 3;",
+        "if 1) print 1;",
+        "while (true print 1;",
+        "for (var i = 0; i < 1 i = i + 1) {}",
+        "{ print 1;",
+        "print (1;\nprint 2",
+        "{ var a = 1; var a = 2; }",
+        "{ var a = 1; var a = 2; }\nprint ;",
+        "{ var a = 1; var a = 2; var a = 3; }",
+        "return 1;\nprint ;",
+        "break;\nprint ;",
+        "{ var a = a; }",
+        "break;",
+        "print \"unterminated",
+        "print 1 @ 2;\nprint ;",
+        "return 1;",
+        "fun f( {}",
+        "fun (a) {}",
+        "fun f(a b) {}",
+        "fun f() print 1;",
+        "while (true) { fun f() { break; } }",
+        "f(1;",
+        "fun f() { return 1 }",
+        &format!("f({});", vec!["1"; 256].join(", ")),
+        &format!("f(\n{},\n2);", vec!["1"; 255].join(", ")),
+    ];
+
+    for source in sources {
+        let (result, _, bytecode) = run(source);
+        assert_eq!(result, InterpretResult::CompileError, "{source}");
+
+        let mut tree_walker = Vec::new();
+        let _ = Lox::with_output(io::sink(), &mut tree_walker).run(source);
+        assert_eq!(
+            bytecode,
+            String::from_utf8(tree_walker).unwrap(),
+            "{source}"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+
+use rlox_bytecode::{InterpretResult, VM};
+
+/// Runs `source` on a fresh VM, collecting garbage before every allocation
+/// if `stress` is set, and returns what it printed.
+fn output_of(source: &str, stress: bool) -> String {
+    let mut output = Vec::new();
+    let mut diagnostics = Vec::new();
+    let result = {
+        let mut vm = VM::with_output(&mut output, &mut diagnostics);
+        vm.set_stress_gc(stress);
+        vm.interpret(source)
+    };
+    assert_eq!(
+        result,
+        InterpretResult::Ok,
+        "{}",
+        String::from_utf8_lossy(&diagnostics)
+    );
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn collecting_on_every_allocation_changes_nothing() {
+    let source = r#"
+fun makeCounter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+
+var counter = makeCounter();
+var last;
+for (var i = 0; i < 50; i = i + 1) {
+  var garbage = "tmp" + "orary";
+  last = counter();
+}
+print last;
+
+var s = "";
+for (var i = 0; i < 20; i = i + 1) s = s + "ab";
+print s;
+"#;
+
+    assert_eq!(output_of(source, true), output_of(source, false));
+}
+
+#[test]
+fn globals_survive_collections_between_runs() {
+    let mut output = Vec::new();
+    {
+        let mut vm = VM::with_output(&mut output, io::sink());
+        vm.set_stress_gc(true);
+        assert_eq!(vm.interpret("var a = \"x\" + \"y\";"), InterpretResult::Ok);
+        assert_eq!(
+            vm.interpret("fun f() { return a + \"z\"; }"),
+            InterpretResult::Ok
+        );
+        assert_eq!(vm.interpret("print a; print f();"), InterpretResult::Ok);
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "xy\nxyz\n");
+}
+
+#[test]
+fn gc_log_goes_to_diagnostics() {
+    let mut diagnostics = Vec::new();
+    {
+        let mut vm = VM::with_output(io::sink(), &mut diagnostics);
+        vm.set_stress_gc(true);
+        vm.set_log_gc(true);
+        assert_eq!(vm.interpret("print \"a\" + \"b\";"), InterpretResult::Ok);
+        vm.set_log_gc(false);
+        assert_eq!(vm.interpret("print -\"c\";"), InterpretResult::RuntimeError);
+    }
+
+    let diagnostics = String::from_utf8(diagnostics).unwrap();
+    let (log, error) = diagnostics.rsplit_once("-- gc end\n").unwrap();
+    assert!(log.starts_with("-- gc begin\n"), "{log}");
+    assert!(log.contains(" allocate "), "{log}");
+    assert!(
+        error.ends_with("Operand must be a number.\n[line 1] in script\n"),
+        "{error}"
+    );
+}
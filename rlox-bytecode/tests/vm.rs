@@ -0,0 +1,42 @@
+use std::io;
+
+use rlox_bytecode::{InterpretResult, VM};
+
+/// Runs `source` on a fresh VM, returning what it printed and reported.
+fn run(source: &str) -> (InterpretResult, String, String) {
+    let mut output = Vec::new();
+    let mut diagnostics = Vec::new();
+    let result = VM::with_output(&mut output, &mut diagnostics).interpret(source);
+
+    (
+        result,
+        String::from_utf8(output).unwrap(),
+        String::from_utf8(diagnostics).unwrap(),
+    )
+}
+
+#[test]
+fn deep_recursion_is_a_stack_overflow() {
+    let (result, _, diagnostics) = run("fun f() { f(); }\nf();");
+    assert_eq!(result, InterpretResult::RuntimeError);
+    assert!(
+        diagnostics.starts_with("Stack overflow.\n[line 1] in f()\n"),
+        "{diagnostics}"
+    );
+    assert!(
+        diagnostics.ends_with("[line 2] in script\n"),
+        "{diagnostics}"
+    );
+
+    // The VM is still usable afterwards.
+    let mut output = Vec::new();
+    {
+        let mut vm = VM::with_output(&mut output, io::sink());
+        assert_eq!(
+            vm.interpret("fun f() { f(); } f();"),
+            InterpretResult::RuntimeError
+        );
+        assert_eq!(vm.interpret("print 1;"), InterpretResult::Ok);
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "1\n");
+}
@@ -0,0 +1,99 @@
+//! Access to `///` doc comments for tools such as documentation generators.
+
+use crate::{error::LoxError, intepreter_structs::Decl, parser::Parser, scanner::Scanner};
+
+/// The kind of declaration a doc comment documents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocKind {
+    Variable,
+    Function,
+    Class,
+    Method,
+}
+
+/// A `///` doc comment together with the declaration that follows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocComment {
+    pub kind: DocKind,
+    /// Name of the declaration. Methods are named `Class.method`.
+    pub name: String,
+    /// Line the declaration's name is on.
+    pub line: u64,
+    /// The comment's lines with their `///` markers removed, joined by `\n`.
+    pub text: String,
+}
+
+/// Parses `source` without running it and returns the doc comments of its
+/// top-level declarations and their methods, in source order.
+///
+/// ```
+/// use rlox::{DocKind, doc_comments};
+///
+/// let docs = doc_comments("/// Adds one.\nfun inc(n) { return n + 1; }").unwrap();
+/// assert_eq!(docs[0].kind, DocKind::Function);
+/// assert_eq!(docs[0].name, "inc");
+/// assert_eq!(docs[0].text, "Adds one.");
+/// ```
+pub fn doc_comments(source: &str) -> Result<Vec<DocComment>, LoxError> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().map_err(LoxError::Scan)?;
+    let declarations = Parser::new(tokens.to_vec())
+        .parse()
+        .map_err(LoxError::Parse)?;
+
+    let mut docs = Vec::new();
+    for declaration in &declarations {
+        match declaration {
+            Decl::Var {
+                identifier, doc, ..
+            } => push_doc(
+                &mut docs,
+                DocKind::Variable,
+                &identifier.lexeme,
+                identifier.line,
+                doc,
+            ),
+            Decl::Fun(function) => push_doc(
+                &mut docs,
+                DocKind::Function,
+                &function.name.lexeme,
+                function.name.line,
+                &function.doc,
+            ),
+            Decl::Class {
+                name, methods, doc, ..
+            } => {
+                push_doc(&mut docs, DocKind::Class, &name.lexeme, name.line, doc);
+                for method in methods {
+                    push_doc(
+                        &mut docs,
+                        DocKind::Method,
+                        &format!("{}.{}", name.lexeme, method.name.lexeme),
+                        method.name.line,
+                        &method.doc,
+                    );
+                }
+            }
+            Decl::Stmt(_) => (),
+        }
+    }
+
+    Ok(docs)
+}
+
+fn push_doc(
+    docs: &mut Vec<DocComment>,
+    kind: DocKind,
+    name: &str,
+    line: u64,
+    doc: &Option<String>,
+) {
+    if let Some(text) = doc {
+        docs.push(DocComment {
+            kind,
+            name: name.to_owned(),
+            line,
+            text: text.clone(),
+        });
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     interpreter::{RuntimeError, Value},
@@ -7,7 +7,7 @@ use crate::{
 
 #[derive(Debug, Default)]
 pub struct Environment {
-    pub enclosing: Option<Box<Environment>>,
+    pub enclosing: Option<Rc<RefCell<Environment>>>,
     values: HashMap<String, Value>,
 }
 
@@ -19,35 +19,72 @@ impl Environment {
         }
     }
 
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            enclosing: Some(enclosing),
+            values: HashMap::new(),
+        }
+    }
+
     pub fn define(&mut self, name: &str, value: Option<Value>) {
         self.values
             .insert(name.to_owned(), value.unwrap_or(Value::Nil));
     }
 
-    pub fn assign(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
-        if self.values.contains_key(name) {
-            self.values.insert(name.to_owned(), value);
+    pub fn assign(&mut self, token: &Token, value: Value) -> Result<(), RuntimeError> {
+        if self.values.contains_key(&token.lexeme) {
+            self.values.insert(token.lexeme.clone(), value);
             Ok(())
-        } else if let Some(enclosing_env) = &mut self.enclosing {
-            enclosing_env.assign(name, value)
+        } else if let Some(enclosing_env) = &self.enclosing {
+            enclosing_env.borrow_mut().assign(token, value)
         } else {
-            Err(RuntimeError {
-                message: format!("Undefined variable '{name}'"),
-            })
+            Err(RuntimeError::new(
+                token,
+                format!("Undefined variable '{}'.", token.lexeme),
+            ))
+        }
+    }
+
+    pub fn get_at(&self, distance: usize, name: &str) -> Value {
+        if distance == 0 {
+            self.values
+                .get(name)
+                .cloned()
+                .expect("Resolved variables should always exist!")
+        } else {
+            self.enclosing
+                .as_ref()
+                .expect("Resolved scopes should always exist!")
+                .borrow()
+                .get_at(distance - 1, name)
+        }
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Value) {
+        if distance == 0 {
+            self.values.insert(name.to_owned(), value);
+        } else {
+            self.enclosing
+                .as_ref()
+                .expect("Resolved scopes should always exist!")
+                .borrow_mut()
+                .assign_at(distance - 1, name, value);
         }
     }
 
     pub fn get(&self, token: &Token) -> Result<Value, RuntimeError> {
-        let value_option = self.values.get(&token.lexeme).cloned();
-
-        match value_option {
-            Some(value) => Ok(value),
-            None => match &self.enclosing {
-                Some(environment) => environment.get(token),
-                None => Err(RuntimeError {
-                    message: format!("Undefined variable {}.", token.lexeme),
-                }),
-            },
+        self.lookup(&token.lexeme).ok_or_else(|| {
+            RuntimeError::new(token, format!("Undefined variable '{}'.", token.lexeme))
+        })
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self
+                .enclosing
+                .as_ref()
+                .and_then(|environment| environment.borrow().lookup(name)),
         }
     }
 }
@@ -0,0 +1,106 @@
+use std::{error::Error, fmt::Display};
+
+use crate::{
+    interpreter::RuntimeError,
+    token::{Span, Token, TokenType},
+};
+
+/// A problem with the program text itself, found before anything runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: u64,
+    pub column: u64,
+    pub span: Span,
+    /// Where on the line the error is, e.g. `" at 'foo'"` or `" at end"`.
+    pub location: String,
+    pub message: String,
+}
+
+impl SyntaxError {
+    pub(crate) fn new(line: u64, column: u64, span: Span, message: &str) -> Self {
+        Self {
+            line,
+            column,
+            span,
+            location: String::new(),
+            message: message.to_owned(),
+        }
+    }
+
+    /// An error at `token`, the way the parser reports them.
+    pub fn at_token(token: &Token, message: &str) -> Self {
+        let location = if token.token_type == TokenType::Eof {
+            " at end".to_owned()
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+
+        Self {
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            location,
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}] Error{}: {}",
+            self.line, self.location, self.message
+        )
+    }
+}
+
+impl Error for SyntaxError {}
+
+#[derive(Debug)]
+pub enum LoxError {
+    Scan(Vec<SyntaxError>),
+    /// The parser runs even if scanning failed, so this also holds the scan
+    /// errors, in source order.
+    Parse(Vec<SyntaxError>),
+    Resolve(Vec<SyntaxError>),
+    Runtime(RuntimeError),
+}
+
+impl LoxError {
+    /// The error for a program whose scanner found `scan_errors` and whose
+    /// parser, run on the tokens that could be scanned, found `parse_errors`.
+    /// `None` if there were neither.
+    pub fn syntax(scan_errors: Vec<SyntaxError>, parse_errors: Vec<SyntaxError>) -> Option<Self> {
+        if parse_errors.is_empty() {
+            return (!scan_errors.is_empty()).then_some(LoxError::Scan(scan_errors));
+        }
+
+        let mut errors = Vec::with_capacity(scan_errors.len() + parse_errors.len());
+        let mut scan_errors = scan_errors.into_iter().peekable();
+        for error in parse_errors {
+            while let Some(scan_error) =
+                scan_errors.next_if(|scan_error| scan_error.span.start <= error.span.start)
+            {
+                errors.push(scan_error);
+            }
+            errors.push(error);
+        }
+        errors.extend(scan_errors);
+        Some(LoxError::Parse(errors))
+    }
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxError::Scan(errors) | LoxError::Parse(errors) | LoxError::Resolve(errors) => {
+                let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            LoxError::Runtime(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for LoxError {}
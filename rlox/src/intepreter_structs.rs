@@ -1,10 +1,10 @@
-use std::fmt::Display;
+use std::{cell::Cell, fmt::Display, rc::Rc};
 
-use crate::token::Token;
+use crate::token::{Span, Token};
 
 #[derive(Debug)]
 pub(super) enum Literal {
-    Number(i64),
+    Number(f64),
     String(String),
     True,
     False,
@@ -73,62 +73,172 @@ impl Display for UnOp {
     }
 }
 
+/// Number of environments between a variable reference and the scope that
+/// declares it, filled in by the resolver. `None` means the variable is global.
+pub(super) type Depth = Cell<Option<usize>>;
+
+/// An expression, with the source range it was parsed from.
+#[derive(Debug)]
+pub(super) struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
 #[derive(Debug)]
-pub(super) enum Expr {
+pub(super) enum ExprKind {
     Literal(Literal),
     Grouping {
         expression: Box<Expr>,
     },
     Unary {
         operator: UnOp,
+        token: Token,
         right: Box<Expr>,
     },
     Binary {
         left: Box<Expr>,
         operator: BinOp,
+        token: Token,
         right: Box<Expr>,
     },
     Variable {
         token: Token,
+        depth: Depth,
     },
     Assign {
         token: Token,
         value: Box<Expr>,
+        depth: Depth,
     },
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    List(Vec<Expr>),
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    GetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    This {
+        keyword: Token,
+        depth: Depth,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        depth: Depth,
+    },
 }
 
 impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for ExprKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Literal(literal) => write!(f, "{literal}"),
-            Expr::Grouping { expression } => write!(f, "(group {expression})"),
-            Expr::Unary { operator, right } => write!(f, "({operator} {right})"),
-            Expr::Binary {
+            ExprKind::Literal(literal) => write!(f, "{literal}"),
+            ExprKind::Grouping { expression } => write!(f, "(group {expression})"),
+            ExprKind::Unary {
+                operator, right, ..
+            } => write!(f, "({operator} {right})"),
+            ExprKind::Binary {
                 left,
                 operator,
                 right,
+                ..
             } => write!(f, "({operator} {left} {right})"),
-            Expr::Variable { token } => write!(f, "{token}"),
-            Expr::Assign { token, value } => write!(f, "({token} {value})"),
-            Expr::Logical {
+            ExprKind::Variable { token, .. } => write!(f, "{token}"),
+            ExprKind::Assign { token, value, .. } => write!(f, "({token} {value})"),
+            ExprKind::Logical {
                 left,
                 operator,
                 right,
             } => write!(f, "({left} {operator} {right})"),
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                write!(f, "(call {callee}")?;
+                for argument in arguments {
+                    write!(f, " {argument}")?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Get { object, name } => write!(f, "(. {object} {})", name.lexeme),
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => write!(f, "(= (. {object} {}) {value})", name.lexeme),
+            ExprKind::List(elements) => {
+                write!(f, "(list")?;
+                for element in elements {
+                    write!(f, " {element}")?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Map { entries, .. } => {
+                write!(f, "(map")?;
+                for (key, value) in entries {
+                    write!(f, " ({key} {value})")?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::GetIndex { object, index, .. } => write!(f, "([] {object} {index})"),
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => write!(f, "(= ([] {object} {index}) {value})"),
+            ExprKind::This { .. } => write!(f, "this"),
+            ExprKind::Super { method, .. } => write!(f, "(super {})", method.lexeme),
         }
     }
 }
 
+/// A statement, with the source range it was parsed from.
 #[derive(Debug)]
+pub(super) struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
 
-pub(super) enum Stmt {
+#[derive(Debug)]
+pub(super) enum StmtKind {
     Expr(Expr),
-    Print(Expr),
+    Print {
+        keyword: Token,
+        value: Expr,
+    },
     Block(Vec<Decl>),
     If {
         condition: Expr,
@@ -138,15 +248,39 @@ pub(super) enum Stmt {
     While {
         condition: Expr,
         body: Box<Stmt>,
+        /// Run after every iteration, including ones cut short by
+        /// `continue`. Only desugared `for` loops have one.
+        increment: Option<Expr>,
+    },
+    Break,
+    Continue,
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
     },
 }
 
 #[derive(Debug)]
-
 pub(super) enum Decl {
-    VarDecl {
+    Var {
         identifier: Token,
         initializer: Option<Expr>,
+        doc: Option<String>,
+    },
+    Fun(Rc<FunDecl>),
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunDecl>>,
+        doc: Option<String>,
     },
     Stmt(Stmt),
 }
+
+#[derive(Debug)]
+pub(super) struct FunDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Decl>,
+    pub doc: Option<String>,
+}
@@ -1,20 +1,29 @@
 use std::{
-    fmt::Display,
-    mem::{replace, take},
+    cell::RefCell, collections::HashMap, error::Error, fmt::Display, io::Write, mem::replace,
+    rc::Rc,
 };
 
 use crate::{
-    Lox,
     environment::Environment,
-    intepreter_structs::{BinOp, Decl, Expr, Literal, Stmt, UnOp},
-    token::TokenType,
+    intepreter_structs::{
+        BinOp, Decl, Depth, Expr, ExprKind, FunDecl, Literal, Stmt, StmtKind, UnOp,
+    },
+    native::NativeFunction,
+    prelude,
+    token::{Span, Token, TokenType},
 };
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     String(String),
-    Number(i64),
+    Number(f64),
     Boolean(bool),
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    List(Rc<LoxList>),
+    Map(Rc<LoxMap>),
     Nil,
 }
 
@@ -22,195 +31,1093 @@ impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
             Value::String(v) => write!(f, "{v}"),
-            Value::Number(v) => write!(f, "{v}"),
+            Value::Number(v) => write_number(f, *v),
             Value::Boolean(v) => write!(f, "{v}"),
+            Value::Function(v) => write!(f, "{v}"),
+            Value::Native(v) => write!(f, "{v}"),
+            Value::Class(v) => write!(f, "{v}"),
+            Value::Instance(v) => write!(f, "{v}"),
+            Value::List(v) => write!(f, "{v}"),
+            Value::Map(v) => write!(f, "{v}"),
             Value::Nil => write!(f, "nil"),
         }
     }
 }
 
+/// Writes `number` the way Lox prints it: with as few digits as it takes to
+/// read back the same number, so integers have no ".0", and like C's `%g`
+/// in exponent notation (`1e+300`) when its exponent is below -4 or at
+/// least 17, the most significant digits an `f64` can need.
+pub fn write_number(f: &mut std::fmt::Formatter<'_>, number: f64) -> std::fmt::Result {
+    let scientific = format!("{number:e}");
+    let Some((mantissa, exponent)) = scientific.split_once('e') else {
+        // `inf` and `NaN` have no exponent.
+        return write!(f, "{number}");
+    };
+    let exponent: i32 = exponent.parse().expect("`{:e}` writes an integer exponent");
+
+    if number == 0.0 || (-4..17).contains(&exponent) {
+        write!(f, "{number}")
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        write!(f, "{mantissa}e{sign}{:02}", exponent.abs())
+    }
+}
+
+impl Value {
+    /// Name of this value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+trait LoxCallable {
+    /// Name shown for this callable's frame in a stack trace.
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> usize;
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError>;
+}
+
 #[derive(Debug)]
-pub struct RuntimeError {
-    pub message: String,
+pub struct LoxFunction {
+    declaration: Rc<FunDecl>,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
 }
-pub struct Interpreter {
-    environment: Environment,
+
+impl LoxFunction {
+    fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        environment.define("this", Some(Value::Instance(instance)));
+
+        LoxFunction {
+            declaration: Rc::clone(&self.declaration),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    fn this(&self) -> Value {
+        self.closure.borrow().get_at(0, "this")
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(&param.lexeme, Some(argument));
+        }
+
+        let value = match interpreter.execute_block(&self.declaration.body, environment) {
+            Ok(()) => Value::Nil,
+            Err(Unwind::Return(value)) => value,
+            Err(Unwind::Error(err)) => return Err(err),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("the parser rejects loop control outside of loops")
+            }
+        };
+
+        if self.is_initializer {
+            Ok(self.this())
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+// Functions, classes and instances are compared by identity, like every other
+// reference type in Lox.
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Rc<LoxFunction>>,
 }
 
-impl Interpreter {
-    pub fn new() -> Self {
+impl LoxClass {
+    fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+impl LoxCallable for Rc<LoxClass> {
+    fn name(&self) -> &str {
+        "init"
+    }
+
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let instance = Rc::new(LoxInstance {
+            class: Rc::clone(self),
+            fields: RefCell::new(HashMap::new()),
+        });
+
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(Rc::clone(&instance))
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+
+impl LoxInstance {
+    fn get(self: &Rc<Self>, name: &Token) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            return Ok(Value::Function(Rc::new(method.bind(Rc::clone(self)))));
+        }
+
+        Err(RuntimeError::new(
+            name,
+            format!("Undefined property '{}'.", name.lexeme),
+        ))
+    }
+
+    fn set(&self, name: &Token, value: Value) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+/// A growable array shared by reference: every copy of a list value sees
+/// changes made through any other.
+#[derive(Debug)]
+pub struct LoxList {
+    elements: RefCell<Vec<Value>>,
+}
+
+impl LoxList {
+    pub(crate) fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: RefCell::new(elements),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    pub(crate) fn push(&self, value: Value) {
+        self.elements.borrow_mut().push(value);
+    }
+
+    pub(crate) fn pop(&self) -> Option<Value> {
+        self.elements.borrow_mut().pop()
+    }
+
+    fn get(&self, index: &Value) -> Result<Value, String> {
+        let index = self.check_index(index)?;
+        Ok(self.elements.borrow()[index].clone())
+    }
+
+    fn set(&self, index: &Value, value: Value) -> Result<(), String> {
+        let index = self.check_index(index)?;
+        self.elements.borrow_mut()[index] = value;
+        Ok(())
+    }
+
+    fn check_index(&self, index: &Value) -> Result<usize, String> {
+        let index = usize::try_from(index)?;
+        let len = self.len();
+        if index < len {
+            Ok(index)
+        } else {
+            Err(format!(
+                "Index {index} out of bounds for list of length {len}."
+            ))
+        }
+    }
+}
+
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for LoxList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        print_once(self, f, "[...]", |f| {
+            write!(f, "[")?;
+            for (i, element) in self.elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{element}")?;
+            }
+            write!(f, "]")
+        })
+    }
+}
+
+thread_local! {
+    /// Addresses of the lists and maps being printed, outermost first.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Prints `container` with `print`, or as `placeholder` if it is already
+/// being printed further out, so that a list or map containing itself,
+/// directly or through other lists and maps, doesn't recurse forever.
+fn print_once<T>(
+    container: &T,
+    f: &mut std::fmt::Formatter<'_>,
+    placeholder: &str,
+    print: impl FnOnce(&mut std::fmt::Formatter<'_>) -> std::fmt::Result,
+) -> std::fmt::Result {
+    let address = (container as *const T).cast::<()>();
+    if PRINTING.with_borrow(|printing| printing.contains(&address)) {
+        return write!(f, "{placeholder}");
+    }
+
+    PRINTING.with_borrow_mut(|printing| printing.push(address));
+    let result = print(f);
+    PRINTING.with_borrow_mut(|printing| printing.pop());
+    result
+}
+
+/// The hashable form of a value used as a map key. Numbers are keyed by their
+/// bits, with `-0` folded into `0` since the two compare equal in Lox.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum MapKey {
+    String(String),
+    Number(u64),
+    Boolean(bool),
+    Nil,
+}
+
+impl TryFrom<&Value> for MapKey {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(v) => Ok(MapKey::String(v.clone())),
+            Value::Number(v) if *v == 0.0 => Ok(MapKey::Number(0.0f64.to_bits())),
+            Value::Number(v) => Ok(MapKey::Number(v.to_bits())),
+            Value::Boolean(v) => Ok(MapKey::Boolean(*v)),
+            Value::Nil => Ok(MapKey::Nil),
+            _ => Err(format!(
+                "A {} can't be used as a map key.",
+                value.type_name()
+            )),
+        }
+    }
+}
+
+/// A hash map shared by reference, like [`LoxList`]. Entries are kept in
+/// insertion order so that printing a map or listing its keys is
+/// deterministic.
+#[derive(Debug)]
+pub struct LoxMap {
+    entries: RefCell<Vec<(Value, Value)>>,
+    /// Position of each key's entry in `entries`.
+    positions: RefCell<HashMap<MapKey, usize>>,
+}
+
+impl LoxMap {
+    fn new() -> Self {
         Self {
-            environment: Environment::new(),
+            entries: RefCell::new(Vec::new()),
+            positions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub(crate) fn has(&self, key: &Value) -> Result<bool, String> {
+        Ok(self
+            .positions
+            .borrow()
+            .contains_key(&MapKey::try_from(key)?))
+    }
+
+    pub(crate) fn keys(&self) -> Vec<Value> {
+        self.entries
+            .borrow()
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub(crate) fn values(&self) -> Vec<Value> {
+        self.entries
+            .borrow()
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn get(&self, key: &Value) -> Result<Value, String> {
+        match self.positions.borrow().get(&MapKey::try_from(key)?) {
+            Some(&position) => Ok(self.entries.borrow()[position].1.clone()),
+            None => Err(format!("Undefined key '{key}'.")),
+        }
+    }
+
+    fn set(&self, key: Value, value: Value) -> Result<(), String> {
+        let mut entries = self.entries.borrow_mut();
+        let position = *self
+            .positions
+            .borrow_mut()
+            .entry(MapKey::try_from(&key)?)
+            .or_insert(entries.len());
+
+        if position == entries.len() {
+            entries.push((key, value));
+        } else {
+            entries[position].1 = value;
         }
+        Ok(())
     }
+}
 
-    pub fn interpret(&mut self, statements: Vec<Decl>) {
-        statements.into_iter().for_each(|s| {
-            if let Err(err) = self.execute(&s) {
-                Lox::runtime_error(&err.message);
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for LoxMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        print_once(self, f, "{...}", |f| {
+            write!(f, "{{")?;
+            for (i, (key, value)) in self.entries.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{key}: {value}")?;
             }
+            write!(f, "}}")
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: u64,
+    pub column: u64,
+    pub span: Span,
+    /// Lox call frames active when the error happened, innermost first.
+    pub trace: Vec<Frame>,
+}
+
+impl RuntimeError {
+    pub(crate) fn new(token: &Token, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// `None` for top-level code.
+    pub function: Option<String>,
+    pub line: u64,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {name}()", self.line),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// Anything that stops a statement from running to completion: either a
+/// genuine runtime error, a `return` travelling up to its enclosing call, or a
+/// `break`/`continue` travelling up to its enclosing loop.
+#[derive(Debug)]
+enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(err: RuntimeError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+/// How many Lox calls may be in progress at once. Every call takes several
+/// native stack frames, so a program recursing without end would otherwise
+/// overflow the native stack and abort the whole process. Unoptimized builds
+/// still fit this many calls in the 2 MiB stack of a spawned thread.
+const MAX_CALL_DEPTH: usize = 256;
+
+pub struct Interpreter<'a> {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// Name of each function currently being called and the line it was
+    /// called from.
+    call_stack: Vec<(String, u64)>,
+    output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter whose globals hold the standard prelude.
+    pub fn new(output: impl Write + 'a) -> Self {
+        let mut interpreter = Self::without_prelude(output);
+        for native in prelude::natives() {
+            interpreter.define_native(native);
+        }
+        interpreter
+    }
+
+    /// Creates an interpreter whose only globals are the built-in list and
+    /// map operations.
+    pub fn without_prelude(output: impl Write + 'a) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+
+        let mut interpreter = Self {
+            environment: Rc::clone(&globals),
+            globals,
+            call_stack: Vec::new(),
+            output: Box::new(output),
+        };
+        for native in prelude::builtins() {
+            interpreter.define_native(native);
+        }
+        interpreter
+    }
+
+    pub fn define_native(&mut self, native: NativeFunction) {
+        let name = native.name().to_owned();
+        self.globals
+            .borrow_mut()
+            .define(&name, Some(Value::Native(Rc::new(native))));
+    }
+
+    pub fn interpret(&mut self, statements: Vec<Decl>) -> Result<(), RuntimeError> {
+        statements
+            .iter()
+            .try_for_each(|s| self.execute(s))
+            .map_err(|unwind| match unwind {
+                Unwind::Error(err) => self.with_trace(err),
+                Unwind::Return(_) => unreachable!("the resolver rejects top-level returns"),
+                Unwind::Break | Unwind::Continue => {
+                    unreachable!("the parser rejects loop control outside of loops")
+                }
+            })
+    }
+
+    /// Records the Lox call stack in `err` the first time it unwinds across
+    /// a call boundary, while the frames it happened in are still known.
+    fn with_trace(&self, mut err: RuntimeError) -> RuntimeError {
+        if !err.trace.is_empty() {
+            return err;
+        }
+
+        let mut line = err.line;
+        for (function, call_line) in self.call_stack.iter().rev() {
+            err.trace.push(Frame {
+                function: Some(function.clone()),
+                line,
+            });
+            line = *call_line;
+        }
+        err.trace.push(Frame {
+            function: None,
+            line,
         });
+
+        err
     }
 
-    fn execute(&mut self, statement: &Decl) -> Result<(), RuntimeError> {
+    // `execute`, `execute_stmt` and `evaluate` recurse once per level of
+    // nesting and several times per Lox call, so they only dispatch. Each
+    // case that needs locals of its own gets its own function, and only
+    // takes stack space while it runs.
+
+    fn execute(&mut self, statement: &Decl) -> Result<(), Unwind> {
         match statement {
-            Decl::Stmt(stmt) => {
-                self.execute_stmt(stmt)?;
-            }
-            Decl::VarDecl {
+            Decl::Stmt(stmt) => self.execute_stmt(stmt),
+            Decl::Var {
                 identifier,
                 initializer,
-            } => {
-                let value = initializer
-                    .as_ref()
-                    .map(|expr| self.evaluate(expr))
-                    .transpose()?;
+                ..
+            } => self.var_declaration(identifier, initializer.as_ref()),
+            Decl::Fun(declaration) => {
+                self.fun_declaration(declaration);
+                Ok(())
+            }
+            Decl::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => self.class_declaration(name, superclass.as_ref(), methods),
+        }
+    }
+
+    fn var_declaration(
+        &mut self,
+        identifier: &Token,
+        initializer: Option<&Expr>,
+    ) -> Result<(), Unwind> {
+        let value = initializer.map(|expr| self.evaluate(expr)).transpose()?;
 
-                self.environment.define(&identifier.lexeme, value);
+        self.environment
+            .borrow_mut()
+            .define(&identifier.lexeme, value);
+        Ok(())
+    }
+
+    fn fun_declaration(&mut self, declaration: &Rc<FunDecl>) {
+        let function = LoxFunction {
+            declaration: Rc::clone(declaration),
+            closure: Rc::clone(&self.environment),
+            is_initializer: false,
+        };
+
+        self.environment.borrow_mut().define(
+            &declaration.name.lexeme,
+            Some(Value::Function(Rc::new(function))),
+        );
+    }
+
+    fn class_declaration(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunDecl>],
+    ) -> Result<(), Unwind> {
+        let superclass = match superclass {
+            Some(expr) => {
+                let ExprKind::Variable { token, .. } = &expr.kind else {
+                    unreachable!("the parser only produces variables as superclasses");
+                };
+                if token.lexeme == name.lexeme {
+                    return Err(
+                        RuntimeError::new(token, "A class can't inherit from itself.").into(),
+                    );
+                }
+                match self.evaluate(expr)? {
+                    Value::Class(class) => Some(class),
+                    _ => {
+                        return Err(RuntimeError::new(token, "Superclass must be a class.").into());
+                    }
+                }
             }
+            None => None,
+        };
+
+        let enclosing = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::new_enclosed(Rc::clone(&enclosing));
+            environment.define("super", Some(Value::Class(Rc::clone(superclass))));
+            self.environment = Rc::new(RefCell::new(environment));
+        }
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let function = LoxFunction {
+                    declaration: Rc::clone(method),
+                    closure: Rc::clone(&self.environment),
+                    is_initializer: method.name.lexeme == "init",
+                };
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+
+        let class = LoxClass {
+            name: name.lexeme.clone(),
+            superclass,
+            methods,
         };
 
+        self.environment = enclosing;
+        self.environment
+            .borrow_mut()
+            .define(&name.lexeme, Some(Value::Class(Rc::new(class))));
         Ok(())
     }
 
-    fn execute_stmt(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
-        match statement {
-            Stmt::Expr(expr) => {
-                self.evaluate(expr)?;
-            }
-            Stmt::Print(expr) => {
-                println!("{}", self.evaluate(expr)?);
+    fn execute_block(&mut self, decls: &[Decl], environment: Environment) -> Result<(), Unwind> {
+        let prev = replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+
+        let mut result = Ok(());
+        for decl in decls {
+            result = self.execute(decl);
+            if result.is_err() {
+                break;
             }
-            Stmt::Block(decls) => {
-                let prev = replace(&mut self.environment, Environment::new());
-                self.environment.enclosing = Some(Box::new(prev));
+        }
 
-                let result = decls.iter().try_for_each(|decl| self.execute(decl));
+        self.environment = prev;
 
-                let prev = take(&mut self.environment.enclosing);
-                self.environment = *prev.expect("Previous environment should always exist!");
+        result
+    }
 
-                result?;
+    fn execute_stmt(&mut self, statement: &Stmt) -> Result<(), Unwind> {
+        match &statement.kind {
+            StmtKind::Expr(expr) => {
+                self.evaluate(expr)?;
+                Ok(())
             }
-            Stmt::If {
+            StmtKind::Print { keyword, value } => self.print_statement(keyword, value),
+            StmtKind::Block(decls) => self.block_statement(decls),
+            StmtKind::If {
                 condition,
                 then_branch,
                 else_branch,
-            } => {
-                let condition_truthiness = self.evaluate(condition)?;
-                if self.is_truthy(&condition_truthiness) {
-                    self.execute_stmt(then_branch)?; // TODO: refactor and remove this Stmt wrapping
-                } else if let Some(stmt) = else_branch {
-                    self.execute_stmt(stmt)?;
-                }
-            }
-            Stmt::While { condition, body } => {
-                let mut condition_truthiness = self.evaluate(condition)?;
+            } => self.if_statement(condition, then_branch, else_branch.as_deref()),
+            StmtKind::While {
+                condition,
+                body,
+                increment,
+            } => self.while_statement(condition, body, increment.as_ref()),
+            StmtKind::Break => Err(Unwind::Break),
+            StmtKind::Continue => Err(Unwind::Continue),
+            StmtKind::Return { value, .. } => self.return_statement(value.as_ref()),
+        }
+    }
 
-                while self.is_truthy(&condition_truthiness) {
-                    self.execute_stmt(body)?;
-                    condition_truthiness = self.evaluate(condition)?;
-                }
-            }
+    fn block_statement(&mut self, decls: &[Decl]) -> Result<(), Unwind> {
+        let environment = Environment::new_enclosed(Rc::clone(&self.environment));
+        self.execute_block(decls, environment)
+    }
+
+    fn print_statement(&mut self, keyword: &Token, value: &Expr) -> Result<(), Unwind> {
+        let value = self.evaluate(value)?;
+        writeln!(self.output, "{value}")
+            .map_err(|err| RuntimeError::new(keyword, format!("Could not write output: {err}.")))?;
+        Ok(())
+    }
+
+    fn if_statement(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> Result<(), Unwind> {
+        let condition_truthiness = self.evaluate(condition)?;
+        if self.is_truthy(&condition_truthiness) {
+            self.execute_stmt(then_branch) // TODO: refactor and remove this Stmt wrapping
+        } else if let Some(stmt) = else_branch {
+            self.execute_stmt(stmt)
+        } else {
+            Ok(())
         }
+    }
 
+    fn while_statement(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<(), Unwind> {
+        let mut condition_truthiness = self.evaluate(condition)?;
+
+        while self.is_truthy(&condition_truthiness) {
+            match self.execute_stmt(body) {
+                Ok(()) | Err(Unwind::Continue) => (),
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+            condition_truthiness = self.evaluate(condition)?;
+        }
         Ok(())
     }
 
+    fn return_statement(&mut self, value: Option<&Expr>) -> Result<(), Unwind> {
+        let value = match value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+
+        Err(Unwind::Return(value))
+    }
+
     fn evaluate(&mut self, expression: &Expr) -> Result<Value, RuntimeError> {
-        match expression {
-            Expr::Literal(literal) => match literal {
-                Literal::Number(v) => Ok(Value::Number(*v)),
-                Literal::String(v) => Ok(Value::String((*v).clone())),
-                Literal::True => Ok(Value::Boolean(true)),
-                Literal::False => Ok(Value::Boolean(false)),
-                Literal::Nil => Ok(Value::Nil),
-            },
-            Expr::Grouping { expression } => self.evaluate(expression),
-            Expr::Unary { operator, right } => {
-                let value = self.evaluate(right)?;
-                match operator {
-                    UnOp::Minus => match value {
-                        Value::Number(v) => Ok(Value::Number(-v)),
-                        _ => Err(RuntimeError {
-                            message: "Operand must be a number!".into(),
-                        }),
-                    },
-                    UnOp::Bang => Ok(Value::Boolean(!self.is_truthy(&value))),
-                }
-            }
-            Expr::Binary {
+        match &expression.kind {
+            ExprKind::Literal(literal) => Ok(match literal {
+                Literal::Number(v) => Value::Number(*v),
+                Literal::String(v) => Value::String(v.clone()),
+                Literal::True => Value::Boolean(true),
+                Literal::False => Value::Boolean(false),
+                Literal::Nil => Value::Nil,
+            }),
+            ExprKind::Grouping { expression } => self.evaluate(expression),
+            ExprKind::Unary {
+                operator,
+                token,
+                right,
+            } => self.unary(operator, token, right),
+            ExprKind::Binary {
                 left,
                 operator,
+                token,
                 right,
-            } => {
-                let left_value = self.evaluate(left)?;
-                let right_value = self.evaluate(right)?;
-
-                match (&left_value, &right_value) {
-                    (Value::Number(l_v), Value::Number(r_v)) => match operator {
-                        BinOp::EqualEqual => Ok(Value::Boolean(l_v == r_v)),
-                        BinOp::BangEqual => Ok(Value::Boolean(l_v != r_v)),
-                        BinOp::Less => Ok(Value::Boolean(l_v < r_v)),
-                        BinOp::LessEqual => Ok(Value::Boolean(l_v <= r_v)),
-                        BinOp::Greater => Ok(Value::Boolean(l_v > r_v)),
-                        BinOp::GreaterEqual => Ok(Value::Boolean(l_v >= r_v)),
-                        BinOp::Plus => Ok(Value::Number(l_v + r_v)),
-                        BinOp::Minus => Ok(Value::Number(l_v - r_v)),
-                        BinOp::Star => Ok(Value::Number(l_v * r_v)),
-                        BinOp::Slash => {
-                            l_v.checked_div(*r_v)
-                                .map(Value::Number)
-                                .ok_or(RuntimeError {
-                                    message: "Division by 0 encountered".to_owned(),
-                                })
-                        }
-                    },
-                    (Value::String(l_v), Value::String(r_v)) => match operator {
-                        BinOp::EqualEqual => Ok(Value::Boolean(l_v == r_v)),
-                        BinOp::BangEqual => Ok(Value::Boolean(l_v != r_v)),
-                        BinOp::Plus => Ok(Value::String(format!("{l_v}{r_v}"))),
-                        _ => Err(RuntimeError {
-                            message: "Operand must be a number!".into(),
-                        }),
-                    },
-                    _ => match operator {
-                        BinOp::EqualEqual => Ok(Value::Boolean(left_value == right_value)),
-                        BinOp::BangEqual => Ok(Value::Boolean(left_value != right_value)),
-                        _ => Err(RuntimeError {
-                            message: "Operands must be two numbers or two strings.".into(),
-                        }),
-                    },
-                }
-            }
-            Expr::Variable { token } => self.environment.get(token),
-            Expr::Assign { token, value } => {
-                let value = self.evaluate(value)?;
-                self.environment.assign(&token.lexeme, value.clone())?;
-                Ok(value)
-            }
-            Expr::Logical {
+            } => self.binary(left, operator, token, right),
+            ExprKind::Variable { token, depth } => self.look_up_variable(token, depth),
+            ExprKind::Assign {
+                token,
+                value,
+                depth,
+            } => self.assign(token, value, depth),
+            ExprKind::Logical {
                 left,
                 operator,
                 right,
-            } => {
-                let left_value = self.evaluate(left)?;
-                match operator.token_type {
-                    TokenType::Or => {
-                        if self.is_truthy(&left_value) {
-                            return Ok(left_value);
-                        }
-                    }
-                    TokenType::And => {
-                        if !self.is_truthy(&left_value) {
-                            return Ok(left_value);
-                        }
-                    }
-                    _ => unreachable!(),
-                };
+            } => self.logical(left, operator, right),
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } => self.call(callee, paren, arguments),
+            ExprKind::Get { object, name } => self.get(object, name),
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => self.set(object, name, value),
+            ExprKind::List(elements) => self.list(elements),
+            ExprKind::Map { brace, entries } => self.map(brace, entries),
+            ExprKind::GetIndex {
+                object,
+                bracket,
+                index,
+            } => self.get_index(object, bracket, index),
+            ExprKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => self.set_index(object, bracket, index, value),
+            ExprKind::This { keyword, depth } => self.look_up_variable(keyword, depth),
+            ExprKind::Super { method, depth, .. } => self.super_method(method, depth),
+        }
+    }
+
+    fn unary(
+        &mut self,
+        operator: &UnOp,
+        token: &Token,
+        right: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(right)?;
+        match operator {
+            UnOp::Minus => match value {
+                Value::Number(v) => Ok(Value::Number(-v)),
+                _ => Err(RuntimeError::new(token, "Operand must be a number.")),
+            },
+            UnOp::Bang => Ok(Value::Boolean(!self.is_truthy(&value))),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expr,
+        operator: &BinOp,
+        token: &Token,
+        right: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let left_value = self.evaluate(left)?;
+        let right_value = self.evaluate(right)?;
+
+        binary_operation(operator, token, &left_value, &right_value)
+    }
+
+    fn assign(
+        &mut self,
+        token: &Token,
+        value: &Expr,
+        depth: &Depth,
+    ) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(value)?;
+        match depth.get() {
+            Some(distance) => {
+                self.environment
+                    .borrow_mut()
+                    .assign_at(distance, &token.lexeme, value.clone())
+            }
+            None => self.globals.borrow_mut().assign(token, value.clone())?,
+        }
+        Ok(value)
+    }
+
+    fn logical(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let left_value = self.evaluate(left)?;
+        match operator.token_type {
+            TokenType::Or => {
+                if self.is_truthy(&left_value) {
+                    return Ok(left_value);
+                }
+            }
+            TokenType::And => {
+                if !self.is_truthy(&left_value) {
+                    return Ok(left_value);
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        self.evaluate(right)
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> Result<Value, RuntimeError> {
+        let callee = self.evaluate(callee)?;
+        let arguments = self.evaluate_all(arguments)?;
+        self.call_value(&callee, paren, arguments)
+    }
 
-                self.evaluate(right)
+    fn call_value(
+        &mut self,
+        callee: &Value,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let callable: &dyn LoxCallable = match callee {
+            // Natives run no Lox code, so they get no frame in stack traces.
+            Value::Native(native) => return call_native(native, paren, &arguments),
+            Value::Function(function) => function.as_ref(),
+            Value::Class(class) => class,
+            _ => {
+                return Err(RuntimeError::new(
+                    paren,
+                    "Can only call functions and classes.",
+                ));
             }
+        };
+
+        check_arity(paren, callable.arity(), arguments.len())?;
+        if self.call_stack.len() == MAX_CALL_DEPTH {
+            return Err(self.with_trace(RuntimeError::new(paren, "Stack overflow.")));
+        }
+
+        self.call_stack
+            .push((callable.name().to_owned(), paren.line));
+        let result = callable
+            .call(self, arguments)
+            .map_err(|err| self.with_trace(err));
+        self.call_stack.pop();
+
+        result
+    }
+
+    fn evaluate_all(&mut self, expressions: &[Expr]) -> Result<Vec<Value>, RuntimeError> {
+        expressions
+            .iter()
+            .map(|expression| self.evaluate(expression))
+            .collect()
+    }
+
+    fn get(&mut self, object: &Expr, name: &Token) -> Result<Value, RuntimeError> {
+        match self.evaluate(object)? {
+            Value::Instance(instance) => instance.get(name),
+            _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+        }
+    }
+
+    fn set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<Value, RuntimeError> {
+        let Value::Instance(instance) = self.evaluate(object)? else {
+            return Err(RuntimeError::new(name, "Only instances have fields."));
+        };
+
+        let value = self.evaluate(value)?;
+        instance.set(name, value.clone());
+        Ok(value)
+    }
+
+    fn list(&mut self, elements: &[Expr]) -> Result<Value, RuntimeError> {
+        let elements = self.evaluate_all(elements)?;
+        Ok(Value::List(Rc::new(LoxList::new(elements))))
+    }
+
+    fn map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<Value, RuntimeError> {
+        let map = LoxMap::new();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            let value = self.evaluate(value)?;
+            map.set(key, value)
+                .map_err(|message| RuntimeError::new(brace, message))?;
+        }
+        Ok(Value::Map(Rc::new(map)))
+    }
+
+    fn get_index(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        match object {
+            Value::List(list) => list.get(&index),
+            Value::Map(map) => map.get(&index),
+            _ => Err("Only lists and maps can be indexed.".to_owned()),
+        }
+        .map_err(|message| RuntimeError::new(bracket, message))
+    }
+
+    fn set_index(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        match object {
+            Value::List(list) => list.set(&index, value.clone()),
+            Value::Map(map) => map.set(index, value.clone()),
+            _ => Err("Only lists and maps can be indexed.".to_owned()),
+        }
+        .map_err(|message| RuntimeError::new(bracket, message))?;
+        Ok(value)
+    }
+
+    fn super_method(&mut self, method: &Token, depth: &Depth) -> Result<Value, RuntimeError> {
+        let distance = depth.get().expect("'super' is always a local variable");
+        let Value::Class(superclass) = self.environment.borrow().get_at(distance, "super") else {
+            unreachable!("'super' is only ever bound to a class");
+        };
+        let Value::Instance(instance) = self.environment.borrow().get_at(distance - 1, "this")
+        else {
+            unreachable!("'this' is only ever bound to an instance");
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(function) => Ok(Value::Function(Rc::new(function.bind(instance)))),
+            None => Err(RuntimeError::new(
+                method,
+                format!("Undefined property '{}'.", method.lexeme),
+            )),
+        }
+    }
+
+    fn look_up_variable(&self, token: &Token, depth: &Depth) -> Result<Value, RuntimeError> {
+        match depth.get() {
+            Some(distance) => Ok(self.environment.borrow().get_at(distance, &token.lexeme)),
+            None => self.globals.borrow().get(token),
         }
     }
 
@@ -222,3 +1129,63 @@ impl Interpreter {
         }
     }
 }
+
+fn call_native(
+    native: &NativeFunction,
+    paren: &Token,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    check_arity(paren, native.arity(), arguments.len())?;
+    native
+        .call(arguments)
+        .map_err(|message| RuntimeError::new(paren, message))
+}
+
+fn check_arity(paren: &Token, arity: usize, count: usize) -> Result<(), RuntimeError> {
+    if arity == count {
+        Ok(())
+    } else {
+        Err(RuntimeError::new(
+            paren,
+            format!("Expected {arity} arguments but got {count}."),
+        ))
+    }
+}
+
+/// Applies a binary operator to operands that have already been evaluated.
+fn binary_operation(
+    operator: &BinOp,
+    token: &Token,
+    left: &Value,
+    right: &Value,
+) -> Result<Value, RuntimeError> {
+    match (left, right) {
+        (Value::Number(l_v), Value::Number(r_v)) => match operator {
+            BinOp::EqualEqual => Ok(Value::Boolean(l_v == r_v)),
+            BinOp::BangEqual => Ok(Value::Boolean(l_v != r_v)),
+            BinOp::Less => Ok(Value::Boolean(l_v < r_v)),
+            BinOp::LessEqual => Ok(Value::Boolean(l_v <= r_v)),
+            BinOp::Greater => Ok(Value::Boolean(l_v > r_v)),
+            BinOp::GreaterEqual => Ok(Value::Boolean(l_v >= r_v)),
+            BinOp::Plus => Ok(Value::Number(l_v + r_v)),
+            BinOp::Minus => Ok(Value::Number(l_v - r_v)),
+            BinOp::Star => Ok(Value::Number(l_v * r_v)),
+            BinOp::Slash => Ok(Value::Number(l_v / r_v)),
+        },
+        (Value::String(l_v), Value::String(r_v)) => match operator {
+            BinOp::EqualEqual => Ok(Value::Boolean(l_v == r_v)),
+            BinOp::BangEqual => Ok(Value::Boolean(l_v != r_v)),
+            BinOp::Plus => Ok(Value::String(format!("{l_v}{r_v}"))),
+            _ => Err(RuntimeError::new(token, "Operands must be numbers.")),
+        },
+        _ => match operator {
+            BinOp::EqualEqual => Ok(Value::Boolean(left == right)),
+            BinOp::BangEqual => Ok(Value::Boolean(left != right)),
+            BinOp::Plus => Err(RuntimeError::new(
+                token,
+                "Operands must be two numbers or two strings.",
+            )),
+            _ => Err(RuntimeError::new(token, "Operands must be numbers.")),
+        },
+    }
+}
@@ -1,60 +1,216 @@
+mod docs;
 mod environment;
+mod error;
 mod intepreter_structs;
 mod interpreter;
+mod native;
 mod parser;
+mod prelude;
+mod resolver;
 mod scanner;
 mod token;
 
+pub use docs::{DocComment, DocKind, doc_comments};
+pub use error::{LoxError, SyntaxError};
+pub use interpreter::{Frame, RuntimeError, Value, write_number};
+pub use scanner::Scanner;
+pub use token::{Span, Token, TokenType};
+
+use std::io::{self, Write};
+
 use interpreter::Interpreter;
+use native::NativeFunction;
 use parser::Parser;
-use scanner::Scanner;
-use token::{Token, TokenType};
+use resolver::Resolver;
 
-pub struct Lox {
-    interpreter: Interpreter,
+pub struct Lox<'a> {
+    interpreter: Interpreter<'a>,
+    diagnostics: Box<dyn Write + 'a>,
 }
 
-impl Lox {
+impl Lox<'static> {
+    /// Creates an interpreter that prints to the process's stdout and
+    /// reports errors on its stderr.
     pub fn new() -> Self {
-        let interpreter = Interpreter::new();
+        Self::with_output(io::stdout(), io::stderr())
+    }
+}
 
-        Self { interpreter }
+impl<'a> Lox<'a> {
+    /// Creates an interpreter that sends everything the program `print`s to
+    /// `output` and a rendered report of every error to `diagnostics`.
+    ///
+    /// Pass `io::sink()` as `diagnostics` to handle the errors returned by
+    /// [`Lox::run`] yourself.
+    pub fn with_output(output: impl Write + 'a, diagnostics: impl Write + 'a) -> Self {
+        Self {
+            interpreter: Interpreter::new(output),
+            diagnostics: Box::new(diagnostics),
+        }
     }
 
-    pub fn run(&mut self, byte: &str) {
-        let mut scanner = Scanner::new(byte);
-        let tokens = scanner.scan_tokens();
+    /// Like [`Lox::with_output`], but without the standard prelude (`clock`,
+    /// `str`, `sqrt`, ...), so scripts can only call the list and map
+    /// operations (`len`, `append`, `keys`, ...) and what is registered with
+    /// [`Lox::register_native`].
+    pub fn without_prelude(output: impl Write + 'a, diagnostics: impl Write + 'a) -> Self {
+        Self {
+            interpreter: Interpreter::without_prelude(output),
+            diagnostics: Box::new(diagnostics),
+        }
+    }
 
-        let mut parser = Parser::new(tokens.to_vec());
+    /// Defines a global function `name` that scripts can call with exactly
+    /// `arity` arguments. Returning `Err(message)` from `function` raises a
+    /// runtime error at the call site.
+    ///
+    /// ```
+    /// use rlox::{Lox, Value};
+    ///
+    /// let mut lox = Lox::new();
+    /// lox.register_native("double", 1, |args| {
+    ///     let n = f64::try_from(&args[0])?;
+    ///     Ok(Value::from(n * 2.0))
+    /// });
+    /// lox.run("print double(21);").unwrap();
+    /// ```
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.interpreter
+            .define_native(NativeFunction::new(name, arity, function));
+    }
 
-        let statements = parser.parse();
+    pub fn run(&mut self, byte: &str) -> Result<(), LoxError> {
+        let result = self.execute(byte);
 
-        self.interpreter.interpret(statements);
-    }
+        if let Err(err) = &result {
+            // Failing to report an error must not hide the error itself.
+            let _ = writeln!(self.diagnostics, "{}", Self::report(byte, err));
+        }
 
-    pub fn error(line: u64, message: &str) {
-        Self::report(line, "", message);
+        result
     }
 
-    pub fn report(line: u64, location: &str, message: &str) {
-        eprintln!("[line {line}] Error{location}: {message}")
+    fn execute(&mut self, byte: &str) -> Result<(), LoxError> {
+        let mut scanner = Scanner::new(byte);
+        let (tokens, scan_errors) = scanner.scan_tokens_and_errors();
+
+        let mut parser = Parser::new(tokens.to_vec());
+
+        let (statements, parse_errors) = match parser.parse() {
+            Ok(statements) => (statements, Vec::new()),
+            Err(errors) => (Vec::new(), errors),
+        };
+        if let Some(error) = LoxError::syntax(scan_errors, parse_errors) {
+            return Err(error);
+        }
+
+        Resolver::new()
+            .resolve(&statements)
+            .map_err(LoxError::Resolve)?;
+
+        self.interpreter
+            .interpret(statements)
+            .map_err(LoxError::Runtime)
     }
 
-    pub fn token_error(token: Token, message: &str) {
-        if token.token_type == TokenType::Eof {
-            Self::report(token.line, " at end", message)
-        } else {
-            Self::report(token.line, &format!(" at '{}'", token.lexeme), message);
+    /// Renders `error` for a terminal: every syntax error is followed by the
+    /// offending line of `source` with the bad range underlined.
+    pub fn report(source: &str, error: &LoxError) -> String {
+        match error {
+            LoxError::Scan(errors) | LoxError::Parse(errors) | LoxError::Resolve(errors) => errors
+                .iter()
+                .map(|error| format!("{error}\n{}", snippet(source, error.span)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            LoxError::Runtime(error) => error.to_string(),
         }
     }
+}
 
-    pub fn runtime_error(message: &str) {
-        eprintln!("Runtime error: {message}");
+fn snippet(source: &str, span: Span) -> String {
+    // Errors at end of input point just past the last non-blank character.
+    let mut start = span.start.min(source.trim_end().len());
+    while !source.is_char_boundary(start) {
+        start -= 1;
     }
+
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line_number = (source[..start].matches('\n').count() + 1).to_string();
+
+    let padding: String = source[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = source
+        .get(start..span.end.clamp(start, line_end))
+        .map_or(1, |text| text.chars().count().max(1));
+    let gutter = " ".repeat(line_number.len());
+
+    format!(
+        "{gutter} |\n{line_number} | {}\n{gutter} | {padding}{}",
+        &source[line_start..line_end],
+        "^".repeat(width)
+    )
 }
 
-impl Default for Lox {
+impl Default for Lox<'static> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_counts_chars_not_bytes() {
+        let source = "print \"héllo\" + é;";
+
+        assert_eq!(
+            snippet(source, Span::new(17, 19)),
+            "  |\n1 | print \"héllo\" + é;\n  |                 ^"
+        );
+        // A span starting inside a char is moved back to the char's start.
+        assert_eq!(
+            snippet(source, Span::new(18, 19)),
+            snippet(source, Span::new(17, 19))
+        );
+    }
+
+    #[test]
+    fn snippet_keeps_tabs_in_the_padding() {
+        assert_eq!(
+            snippet("\tprint x;", Span::new(7, 8)),
+            "  |\n1 | \tprint x;\n  | \t      ^"
+        );
+    }
+
+    #[test]
+    fn snippet_at_end_of_input_points_past_the_last_character() {
+        assert_eq!(
+            snippet("print 1", Span::new(7, 7)),
+            "  |\n1 | print 1\n  |        ^"
+        );
+        assert_eq!(
+            snippet("x;\n\n   ", Span::new(8, 8)),
+            "  |\n1 | x;\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn snippet_stops_underlining_at_the_end_of_the_line() {
+        assert_eq!(
+            snippet("print \"a\nb\";", Span::new(6, 11)),
+            "  |\n1 | print \"a\n  |       ^^"
+        );
+    }
+}
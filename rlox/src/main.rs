@@ -6,12 +6,15 @@ use std::{
     process,
 };
 
-use rlox::Lox;
+use rlox::{Lox, LoxError};
 
 fn run_file(path: &str) -> Result<(), Box<dyn Error + 'static>> {
     let data: Vec<u8> = fs::read(path)?;
+    let source = String::from_utf8(data)?;
 
-    Lox::new().run(&String::from_utf8(data)?);
+    if let Err(err) = Lox::new().run(&source) {
+        process::exit(exit_code(&err));
+    }
 
     Ok(())
 }
@@ -33,7 +36,15 @@ fn run_prompt() -> Result<(), Box<dyn Error + 'static>> {
             break Ok(());
         }
 
-        lox.run(&line);
+        // Errors have already been reported; the session carries on.
+        let _ = lox.run(&line);
+    }
+}
+
+fn exit_code(err: &LoxError) -> i32 {
+    match err {
+        LoxError::Scan(_) | LoxError::Parse(_) | LoxError::Resolve(_) => 65,
+        LoxError::Runtime(_) => 70,
     }
 }
 
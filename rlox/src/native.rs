@@ -0,0 +1,175 @@
+use std::{
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::interpreter::{LoxList, LoxMap, Value};
+
+type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// A function implemented in Rust and callable from Lox.
+///
+/// Returning `Err(message)` raises a Lox runtime error with that message at
+/// the call site.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, String> {
+        (self.function)(arguments)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Nil, Into::into)
+    }
+}
+
+fn type_error(expected: &str, value: &Value) -> String {
+    format!("Expected {expected} but got {}.", value.type_name())
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(v) => Ok(*v),
+            _ => Err(type_error("a number", value)),
+        }
+    }
+}
+
+/// Accepts a number that can be used as a position, such as a list index.
+impl TryFrom<&Value> for usize {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let number = f64::try_from(value)?;
+        if number < 0.0 || number.fract() != 0.0 {
+            return Err(format!(
+                "Index must be a non-negative integer, got {number}."
+            ));
+        }
+        Ok(number as usize)
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(v) => Ok(*v),
+            _ => Err(type_error("a boolean", value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(v) => Ok(v.clone()),
+            _ => Err(type_error("a string", value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Rc<LoxList> {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(v) => Ok(Rc::clone(v)),
+            _ => Err(type_error("a list", value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Rc<LoxMap> {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Map(v) => Ok(Rc::clone(v)),
+            _ => Err(type_error("a map", value)),
+        }
+    }
+}
@@ -1,14 +1,23 @@
-use std::mem::discriminant;
+use std::{
+    mem::{discriminant, replace, take},
+    rc::Rc,
+};
 
 use crate::{
-    Lox,
-    intepreter_structs::{BinOp, Decl, Expr, Literal, Stmt, UnOp},
-    token::{Token, TokenType},
+    error::SyntaxError,
+    intepreter_structs::{
+        BinOp, Decl, Depth, Expr, ExprKind, FunDecl, Literal, Stmt, StmtKind, UnOp,
+    },
+    token::{Span, Token, TokenType},
 };
 
 pub struct Parser {
     pub tokens: Vec<Token>,
     pub current: usize,
+    errors: Vec<SyntaxError>,
+    /// How many loops enclose the code being parsed, within the current
+    /// function.
+    loop_depth: usize,
 }
 
 #[derive(Debug)]
@@ -16,21 +25,40 @@ struct ParserError;
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            loop_depth: 0,
+        }
     }
 
-    pub fn parse(&mut self) -> Vec<Decl> {
+    /// Parses the whole token stream, recovering after each syntax error so
+    /// that every error in the file is reported at once.
+    pub fn parse(&mut self) -> Result<Vec<Decl>, Vec<SyntaxError>> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.declaration().unwrap());
+            if let Some(decl) = self.declaration() {
+                statements.push(decl);
+            }
         }
 
-        statements
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(take(&mut self.errors))
+        }
     }
 
     fn declaration(&mut self) -> Option<Decl> {
+        let doc = self.peek().doc;
         let res = if self.match_tokens(&[TokenType::Var]) {
-            self.var_declaration()
+            self.var_declaration(doc)
+        } else if self.match_tokens(&[TokenType::Fun]) {
+            self.function("function", doc)
+                .map(|function| Decl::Fun(Rc::new(function)))
+        } else if self.match_tokens(&[TokenType::Class]) {
+            self.class_declaration(doc)
         } else {
             self.statement().map(Decl::Stmt)
         };
@@ -41,7 +69,7 @@ impl Parser {
         res.ok()
     }
 
-    fn var_declaration(&mut self) -> Result<Decl, ParserError> {
+    fn var_declaration(&mut self, doc: Option<String>) -> Result<Decl, ParserError> {
         let token = self.consume(
             TokenType::Identifier("a".to_owned()),
             "Expect variable name.",
@@ -58,16 +86,103 @@ impl Parser {
             "Expect ';' after variable declaration.",
         )?;
 
-        Ok(Decl::VarDecl {
+        Ok(Decl::Var {
             identifier: token,
             initializer,
+            doc,
+        })
+    }
+
+    fn class_declaration(&mut self, doc: Option<String>) -> Result<Decl, ParserError> {
+        let name = self.consume(TokenType::Identifier("a".to_owned()), "Expect class name.")?;
+
+        let superclass = if self.match_tokens(&[TokenType::Less]) {
+            let token = self.consume(
+                TokenType::Identifier("a".to_owned()),
+                "Expect superclass name.",
+            )?;
+            Some(Expr {
+                span: token.span,
+                kind: ExprKind::Variable {
+                    token,
+                    depth: Depth::default(),
+                },
+            })
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let doc = self.peek().doc;
+            methods.push(Rc::new(self.function("method", doc)?));
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Decl::Class {
+            name,
+            superclass,
+            methods,
+            doc,
+        })
+    }
+
+    fn function(&mut self, kind: &str, doc: Option<String>) -> Result<FunDecl, ParserError> {
+        let name = self.consume(
+            TokenType::Identifier("a".to_owned()),
+            &format!("Expect {kind} name."),
+        )?;
+
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {kind} name."),
+        )?;
+
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    self.error(self.peek(), "Can't have more than 255 parameters.");
+                }
+
+                params.push(self.consume(
+                    TokenType::Identifier("a".to_owned()),
+                    "Expect parameter name.",
+                )?);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {kind} body."),
+        )?;
+        // A function body starts outside of any loop, even if the function
+        // itself is declared inside one.
+        let enclosing_loops = replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = enclosing_loops;
+
+        Ok(FunDecl {
+            name,
+            params,
+            body: body?,
+            doc,
         })
     }
 
     fn statement(&mut self) -> Result<Stmt, ParserError> {
-        if self.match_tokens(&[TokenType::Print]) {
+        let start = self.current;
+        let kind = if self.match_tokens(&[TokenType::Print]) {
             self.print_statement()
-        } else if self.match_tokens(&[TokenType::LeftBrace]) {
+        } else if !self.starts_map_literal() && self.match_tokens(&[TokenType::LeftBrace]) {
             self.block_statement()
         } else if self.match_tokens(&[TokenType::If]) {
             self.if_statement()
@@ -75,43 +190,78 @@ impl Parser {
             self.while_statement()
         } else if self.match_tokens(&[TokenType::For]) {
             self.for_statement()
+        } else if self.match_tokens(&[TokenType::Return]) {
+            self.return_statement()
+        } else if self.match_tokens(&[TokenType::Break]) {
+            self.loop_control(StmtKind::Break)
+        } else if self.match_tokens(&[TokenType::Continue]) {
+            self.loop_control(StmtKind::Continue)
         } else {
-            Ok(self.expression_statement()?)
-        }
+            self.expression_statement()
+        }?;
+
+        Ok(self.stmt(start, kind))
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn print_statement(&mut self) -> Result<StmtKind, ParserError> {
+        let keyword = self.previous();
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Print(value))
+        Ok(StmtKind::Print { keyword, value })
     }
 
-    fn block_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn block_statement(&mut self) -> Result<StmtKind, ParserError> {
+        Ok(StmtKind::Block(self.block()?))
+    }
+
+    /// A `{` at the start of a statement opens a block unless it is followed
+    /// by a single token and a `:`, as in `{"a": 1}[key];`, which can only be
+    /// a map literal. `{}` on its own is always an empty block.
+    fn starts_map_literal(&self) -> bool {
+        self.check(&TokenType::LeftBrace)
+            && self
+                .tokens
+                .get(self.current + 2)
+                .is_some_and(|token| token.token_type == TokenType::Colon)
+    }
+
+    fn block(&mut self) -> Result<Vec<Decl>, ParserError> {
         let mut statements = Vec::new();
-        while !self.check(&TokenType::RightBrace)
-            && !self.is_at_end()
-            && let Some(decl) = self.declaration()
-        {
-            statements.push(decl);
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(decl) = self.declaration() {
+                statements.push(decl);
+            }
         }
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
-        Ok(Stmt::Block(statements))
+        Ok(statements)
+    }
+
+    fn return_statement(&mut self) -> Result<StmtKind, ParserError> {
+        let keyword = self.previous();
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(StmtKind::Return { keyword, value })
     }
 
-    fn if_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn if_statement(&mut self) -> Result<StmtKind, ParserError> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
         let then_branch = self.statement()?;
         if self.match_tokens(&[TokenType::Else]) {
-            Ok(Stmt::If {
+            Ok(StmtKind::If {
                 condition,
                 then_branch: Box::new(then_branch),
                 else_branch: Some(Box::new(self.statement()?)),
             })
         } else {
-            Ok(Stmt::If {
+            Ok(StmtKind::If {
                 condition,
                 then_branch: Box::new(then_branch),
                 else_branch: None,
@@ -119,29 +269,63 @@ impl Parser {
         }
     }
 
-    fn while_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn while_statement(&mut self) -> Result<StmtKind, ParserError> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after while condition.")?;
-        let body = self.statement()?;
-        Ok(Stmt::While {
+        let body = self.loop_body()?;
+        Ok(StmtKind::While {
             condition,
             body: Box::new(body),
+            increment: None,
         })
     }
 
-    fn for_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn loop_body(&mut self) -> Result<Stmt, ParserError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    /// Parses the rest of a `break` or `continue` statement.
+    fn loop_control(&mut self, stmt: StmtKind) -> Result<StmtKind, ParserError> {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            self.error(
+                keyword.clone(),
+                &format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            );
+        }
+        self.consume(
+            TokenType::Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+        Ok(stmt)
+    }
+
+    fn for_statement(&mut self) -> Result<StmtKind, ParserError> {
+        let start = self.current - 1;
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
         let initializer = if self.match_tokens(&[TokenType::Semicolon]) {
             None
         } else if self.match_tokens(&[TokenType::Var]) {
-            Some(self.var_declaration()?)
+            Some(self.var_declaration(None)?)
         } else {
-            Some(Decl::Stmt(Stmt::Expr(self.expression()?)))
+            let expr = self.expression()?;
+            Some(Decl::Stmt(Stmt {
+                span: expr.span,
+                kind: StmtKind::Expr(expr),
+            }))
         };
 
         let condition = if self.check(&TokenType::Semicolon) {
-            Expr::Literal(Literal::True)
+            // An empty condition is true, and takes up no source.
+            let at = self.peek().span.start;
+            Expr {
+                kind: ExprKind::Literal(Literal::True),
+                span: Span::new(at, at),
+            }
         } else {
             self.expression()?
         };
@@ -156,40 +340,36 @@ impl Parser {
 
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
+        let body = self.loop_body()?;
 
         /*
         desugaring for -> while
 
-        for (var i = 0; i < 10; i = i + 1) =>
+        for (var i = 0; i < 10; i = i + 1) body =>
         {
             var i = 0;
-            while (i < 10) {
-                i = i + 1;
-            }
+            while (i < 10) body, then i = i + 1
         }
 
+        The increment is kept apart from the body so that `continue` still
+        runs it.
         */
-        if let Some(incr) = increment {
-            body = Stmt::Block(vec![Decl::Stmt(body), Decl::Stmt(Stmt::Expr(incr))])
-        }
-
-        body = Stmt::While {
+        let body = StmtKind::While {
             condition,
             body: Box::new(body),
+            increment,
         };
 
-        if let Some(init) = initializer {
-            body = Stmt::Block(vec![init, Decl::Stmt(body)]);
-        }
-
-        Ok(body)
+        Ok(match initializer {
+            Some(init) => StmtKind::Block(vec![init, Decl::Stmt(self.stmt(start, body))]),
+            None => body,
+        })
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, ParserError> {
+    fn expression_statement(&mut self) -> Result<StmtKind, ParserError> {
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
-        Ok(Stmt::Expr(value))
+        Ok(StmtKind::Expr(value))
     }
 
     fn expression(&mut self) -> Result<Expr, ParserError> {
@@ -197,76 +377,122 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let expr = self.or()?;
         if self.match_tokens(&[TokenType::Equal]) {
             let equals = self.previous();
             let value = self.assignment()?;
-            if let Expr::Variable { token } = expr {
-                return Ok(Expr::Assign {
-                    token,
-                    value: Box::new(value),
-                });
+            match expr.kind {
+                ExprKind::Variable { token, .. } => {
+                    return Ok(self.expr(
+                        start,
+                        ExprKind::Assign {
+                            token,
+                            value: Box::new(value),
+                            depth: Depth::default(),
+                        },
+                    ));
+                }
+                ExprKind::Get { object, name } => {
+                    return Ok(self.expr(
+                        start,
+                        ExprKind::Set {
+                            object,
+                            name,
+                            value: Box::new(value),
+                        },
+                    ));
+                }
+                ExprKind::GetIndex {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    return Ok(self.expr(
+                        start,
+                        ExprKind::SetIndex {
+                            object,
+                            bracket,
+                            index,
+                            value: Box::new(value),
+                        },
+                    ));
+                }
+                _ => (),
             }
-            Lox::token_error(equals, "Invalid assignment target.");
-            return Err(ParserError);
+            return Err(self.error(equals, "Invalid assignment target."));
         }
 
         Ok(expr)
     }
 
     fn or(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut expr = self.and()?;
         while self.match_tokens(&[TokenType::Or]) {
             let operator = self.previous();
             let right = self.and()?;
 
-            expr = Expr::Logical {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+            expr = self.expr(
+                start,
+                ExprKind::Logical {
+                    left: Box::new(expr),
+                    operator,
+                    right: Box::new(right),
+                },
+            );
         }
 
         Ok(expr)
     }
 
     fn and(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut expr = self.equality()?;
         while self.match_tokens(&[TokenType::And]) {
             let operator = self.previous();
             let right = self.equality()?;
 
-            expr = Expr::Logical {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+            expr = self.expr(
+                start,
+                ExprKind::Logical {
+                    left: Box::new(expr),
+                    operator,
+                    right: Box::new(right),
+                },
+            );
         }
 
         Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut left = self.comparison()?;
 
         while self.match_tokens(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
             let right = self.comparison()?;
-            left = Expr::Binary {
-                left: Box::new(left),
-                operator: match operator.token_type {
-                    TokenType::BangEqual => BinOp::BangEqual,
-                    TokenType::EqualEqual => BinOp::EqualEqual,
-                    _ => unreachable!(),
+            left = self.expr(
+                start,
+                ExprKind::Binary {
+                    left: Box::new(left),
+                    operator: match operator.token_type {
+                        TokenType::BangEqual => BinOp::BangEqual,
+                        TokenType::EqualEqual => BinOp::EqualEqual,
+                        _ => unreachable!(),
+                    },
+                    token: operator,
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            };
+            );
         }
 
         Ok(left)
     }
 
     fn comparison(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut left = self.term()?;
 
         while self.match_tokens(&[
@@ -277,117 +503,279 @@ impl Parser {
         ]) {
             let operator = self.previous();
             let right = self.term()?;
-            left = Expr::Binary {
-                left: Box::new(left),
-                operator: match operator.token_type {
-                    TokenType::Greater => BinOp::Greater,
-                    TokenType::GreaterEqual => BinOp::GreaterEqual,
-                    TokenType::Less => BinOp::Less,
-                    TokenType::LessEqual => BinOp::LessEqual,
-                    _ => unreachable!(),
+            left = self.expr(
+                start,
+                ExprKind::Binary {
+                    left: Box::new(left),
+                    operator: match operator.token_type {
+                        TokenType::Greater => BinOp::Greater,
+                        TokenType::GreaterEqual => BinOp::GreaterEqual,
+                        TokenType::Less => BinOp::Less,
+                        TokenType::LessEqual => BinOp::LessEqual,
+                        _ => unreachable!(),
+                    },
+                    token: operator,
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            };
+            );
         }
 
         Ok(left)
     }
 
     fn term(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut left = self.factor()?;
 
         while self.match_tokens(&[TokenType::Plus, TokenType::Minus]) {
             let operator = self.previous();
             let right = self.factor()?;
-            left = Expr::Binary {
-                left: Box::new(left),
-                operator: match operator.token_type {
-                    TokenType::Plus => BinOp::Plus,
-                    TokenType::Minus => BinOp::Minus,
-                    _ => unreachable!(),
+            left = self.expr(
+                start,
+                ExprKind::Binary {
+                    left: Box::new(left),
+                    operator: match operator.token_type {
+                        TokenType::Plus => BinOp::Plus,
+                        TokenType::Minus => BinOp::Minus,
+                        _ => unreachable!(),
+                    },
+                    token: operator,
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            };
+            );
         }
 
         Ok(left)
     }
 
     fn factor(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         let mut left = self.unary()?;
 
         while self.match_tokens(&[TokenType::Slash, TokenType::Star]) {
             let operator = self.previous();
             let right = self.unary()?;
-            left = Expr::Binary {
-                left: Box::new(left),
-                operator: match operator.token_type {
-                    TokenType::Slash => BinOp::Slash,
-                    TokenType::Star => BinOp::Star,
-                    _ => unreachable!(),
+            left = self.expr(
+                start,
+                ExprKind::Binary {
+                    left: Box::new(left),
+                    operator: match operator.token_type {
+                        TokenType::Slash => BinOp::Slash,
+                        TokenType::Star => BinOp::Star,
+                        _ => unreachable!(),
+                    },
+                    token: operator,
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            };
+            );
         }
 
         Ok(left)
     }
 
     fn unary(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
         if self.match_tokens(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
             let right = self.unary()?;
-            return Ok(Expr::Unary {
-                operator: match operator.token_type {
-                    TokenType::Bang => UnOp::Bang,
-                    TokenType::Minus => UnOp::Minus,
-                    _ => unreachable!(),
+            return Ok(self.expr(
+                start,
+                ExprKind::Unary {
+                    operator: match operator.token_type {
+                        TokenType::Bang => UnOp::Bang,
+                        TokenType::Minus => UnOp::Minus,
+                        _ => unreachable!(),
+                    },
+                    token: operator,
+                    right: Box::new(right),
                 },
-                right: Box::new(right),
-            });
+            ));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_tokens(&[TokenType::LeftParen]) {
+                expr = self.finish_call(start, expr)?;
+            } else if self.match_tokens(&[TokenType::Dot]) {
+                let name = self.consume(
+                    TokenType::Identifier("a".to_owned()),
+                    "Expect property name after '.'.",
+                )?;
+                expr = self.expr(
+                    start,
+                    ExprKind::Get {
+                        object: Box::new(expr),
+                        name,
+                    },
+                );
+            } else if self.match_tokens(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = self.expr(
+                    start,
+                    ExprKind::GetIndex {
+                        object: Box::new(expr),
+                        bracket,
+                        index: Box::new(index),
+                    },
+                );
+            } else {
+                break;
+            }
         }
 
-        self.primary()
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, start: usize, callee: Expr) -> Result<Expr, ParserError> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    self.error(self.peek(), "Can't have more than 255 arguments.");
+                }
+                arguments.push(self.expression()?);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+
+        Ok(self.expr(
+            start,
+            ExprKind::Call {
+                callee: Box::new(callee),
+                paren,
+                arguments,
+            },
+        ))
     }
 
     fn primary(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current;
+        let kind = self.primary_kind()?;
+        Ok(self.expr(start, kind))
+    }
+
+    fn primary_kind(&mut self) -> Result<ExprKind, ParserError> {
         if self.match_tokens(&[TokenType::False]) {
-            return Ok(Expr::Literal(Literal::False));
+            return Ok(ExprKind::Literal(Literal::False));
         }
 
         if self.match_tokens(&[TokenType::True]) {
-            return Ok(Expr::Literal(Literal::True));
+            return Ok(ExprKind::Literal(Literal::True));
         }
 
         if self.match_tokens(&[TokenType::Nil]) {
-            return Ok(Expr::Literal(Literal::Nil));
+            return Ok(ExprKind::Literal(Literal::Nil));
         }
 
-        if self.match_tokens(&[TokenType::Number(-1), TokenType::String("a".to_owned())]) {
+        if self.match_tokens(&[TokenType::Number(0.0), TokenType::String("a".to_owned())]) {
             let literal = match self.previous().token_type {
                 TokenType::Number(v) => Literal::Number(v),
                 TokenType::String(v) => Literal::String(v),
                 _ => unreachable!(),
             };
-            return Ok(Expr::Literal(literal));
+            return Ok(ExprKind::Literal(literal));
         }
 
         if self.match_tokens(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
-            return Ok(Expr::Grouping {
+            return Ok(ExprKind::Grouping {
                 expression: Box::new(expr),
             });
         }
 
+        if self.match_tokens(&[TokenType::LeftBracket]) {
+            let mut elements = Vec::new();
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.match_tokens(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(ExprKind::List(elements));
+        }
+
+        if self.match_tokens(&[TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut entries = Vec::new();
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    entries.push((key, self.expression()?));
+                    if !self.match_tokens(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(ExprKind::Map { brace, entries });
+        }
+
+        if self.match_tokens(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(
+                TokenType::Identifier("a".to_owned()),
+                "Expect superclass method name.",
+            )?;
+            return Ok(ExprKind::Super {
+                keyword,
+                method,
+                depth: Depth::default(),
+            });
+        }
+
+        if self.match_tokens(&[TokenType::This]) {
+            return Ok(ExprKind::This {
+                keyword: self.previous(),
+                depth: Depth::default(),
+            });
+        }
+
         if self.match_tokens(&[TokenType::Identifier("a".into())]) {
-            return Ok(Expr::Variable {
+            return Ok(ExprKind::Variable {
                 token: self.previous(),
+                depth: Depth::default(),
             });
         }
 
-        Lox::token_error(self.peek(), "Expect expression.");
-        Err(ParserError)
+        Err(self.error(self.peek(), "Expect expression."))
+    }
+
+    /// Wraps `kind` in an expression spanning from the token at `start` to
+    /// the last token consumed.
+    fn expr(&self, start: usize, kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: self.span_from(start),
+        }
+    }
+
+    fn stmt(&self, start: usize, kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            span: self.span_from(start),
+        }
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.tokens[start].span.start, self.previous().span.end)
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, ParserError> {
@@ -395,8 +783,12 @@ impl Parser {
             return Ok(self.advance());
         }
 
-        Lox::token_error(self.peek(), message);
-        Err(ParserError)
+        Err(self.error(self.peek(), message))
+    }
+
+    fn error(&mut self, token: Token, message: &str) -> ParserError {
+        self.errors.push(SyntaxError::at_token(&token, message));
+        ParserError
     }
 
     fn match_tokens(&mut self, token_types: &[TokenType]) -> bool {
@@ -448,7 +840,9 @@ impl Parser {
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue => return,
                 _ => (),
             }
 
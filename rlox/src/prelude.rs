@@ -0,0 +1,116 @@
+//! Natives every interpreter starts with unless it is created with
+//! `without_prelude`, and the built-in ones it always starts with.
+
+use std::{
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    interpreter::{LoxList, LoxMap, Value},
+    native::NativeFunction,
+};
+
+/// Operations on lists and maps. Scripts can write list and map literals whatever natives the
+/// embedder registers, so these are defined even by `without_prelude`.
+pub(crate) fn builtins() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("len", 1, |args| {
+            let len = match &args[0] {
+                Value::List(list) => list.len(),
+                Value::Map(map) => map.len(),
+                value => String::try_from(value)
+                    .map_err(|_| {
+                        format!(
+                            "Expected a string, list or map but got {}.",
+                            value.type_name()
+                        )
+                    })?
+                    .chars()
+                    .count(),
+            };
+            Ok(Value::from(len as f64))
+        }),
+        NativeFunction::new("append", 2, |args| {
+            Rc::<LoxList>::try_from(&args[0])?.push(args[1].clone());
+            Ok(Value::Nil)
+        }),
+        NativeFunction::new("pop", 1, |args| {
+            Rc::<LoxList>::try_from(&args[0])?
+                .pop()
+                .ok_or_else(|| "Can't pop from an empty list.".to_owned())
+        }),
+        NativeFunction::new("keys", 1, |args| {
+            let keys = Rc::<LoxMap>::try_from(&args[0])?.keys();
+            Ok(Value::List(Rc::new(LoxList::new(keys))))
+        }),
+        NativeFunction::new("values", 1, |args| {
+            let values = Rc::<LoxMap>::try_from(&args[0])?.values();
+            Ok(Value::List(Rc::new(LoxList::new(values))))
+        }),
+        NativeFunction::new("has", 2, |args| {
+            Ok(Value::from(
+                Rc::<LoxMap>::try_from(&args[0])?.has(&args[1])?,
+            ))
+        }),
+    ]
+}
+
+pub(crate) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("clock", 0, |_| {
+            let elapsed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| err.to_string())?;
+            Ok(Value::from(elapsed.as_secs_f64()))
+        }),
+        NativeFunction::new("str", 1, |args| Ok(Value::from(args[0].to_string()))),
+        NativeFunction::new("num", 1, |args| {
+            let string = String::try_from(&args[0])?;
+            Ok(Value::from(string.trim().parse::<f64>().ok()))
+        }),
+        NativeFunction::new("substr", 3, |args| {
+            let string = String::try_from(&args[0])?;
+            let start = usize::try_from(&args[1])?;
+            let end = usize::try_from(&args[2])?;
+            let length = string.chars().count();
+            if start > end || end > length {
+                return Err(format!(
+                    "Substring {start}..{end} out of range for string of length {length}."
+                ));
+            }
+            Ok(Value::from(
+                string
+                    .chars()
+                    .skip(start)
+                    .take(end - start)
+                    .collect::<String>(),
+            ))
+        }),
+        NativeFunction::new("upper", 1, |args| {
+            Ok(Value::from(String::try_from(&args[0])?.to_uppercase()))
+        }),
+        NativeFunction::new("lower", 1, |args| {
+            Ok(Value::from(String::try_from(&args[0])?.to_lowercase()))
+        }),
+        NativeFunction::new("abs", 1, |args| {
+            Ok(Value::from(f64::try_from(&args[0])?.abs()))
+        }),
+        NativeFunction::new("min", 2, |args| {
+            Ok(Value::from(
+                f64::try_from(&args[0])?.min(f64::try_from(&args[1])?),
+            ))
+        }),
+        NativeFunction::new("max", 2, |args| {
+            Ok(Value::from(
+                f64::try_from(&args[0])?.max(f64::try_from(&args[1])?),
+            ))
+        }),
+        NativeFunction::new("floor", 1, |args| {
+            Ok(Value::from(f64::try_from(&args[0])?.floor()))
+        }),
+        NativeFunction::new("sqrt", 1, |args| {
+            Ok(Value::from(f64::try_from(&args[0])?.sqrt()))
+        }),
+    ]
+}
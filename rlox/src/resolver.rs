@@ -0,0 +1,316 @@
+use std::{collections::HashMap, mem::take};
+
+use crate::{
+    error::SyntaxError,
+    intepreter_structs::{Decl, Depth, Expr, ExprKind, FunDecl, Stmt, StmtKind},
+    token::{Span, Token},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Walks the tree once before it is interpreted, recording in every variable
+/// reference how many scopes away its declaration lives.
+pub struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<SyntaxError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(super) fn resolve(&mut self, statements: &[Decl]) -> Result<(), Vec<SyntaxError>> {
+        self.resolve_decls(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(take(&mut self.errors))
+        }
+    }
+
+    fn resolve_decls(&mut self, statements: &[Decl]) {
+        statements.iter().for_each(|decl| self.resolve_decl(decl));
+    }
+
+    fn resolve_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var {
+                identifier,
+                initializer,
+                ..
+            } => {
+                self.declare(identifier);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(identifier);
+            }
+            Decl::Fun(function) => {
+                self.declare(&function.name);
+                self.define(&function.name);
+
+                self.resolve_function(function, FunctionType::Function);
+            }
+            Decl::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+
+                    self.begin_scope();
+                    self.define_name("super");
+                }
+
+                self.begin_scope();
+                self.define_name("this");
+
+                for method in methods {
+                    let declaration = if method.name.lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+
+                    self.resolve_function(method, declaration);
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Decl::Stmt(stmt) => self.resolve_stmt(stmt),
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(expr) | StmtKind::Print { value: expr, .. } => self.resolve_expr(expr),
+            StmtKind::Block(decls) => {
+                self.begin_scope();
+                self.resolve_decls(decls);
+                self.end_scope();
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            StmtKind::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => (),
+            StmtKind::Return { keyword, value } => {
+                if self.current_function == FunctionType::None {
+                    self.error_spanning(keyword, stmt.span, "Can't return from top-level code.");
+                }
+
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error_spanning(
+                            keyword,
+                            value.span,
+                            "Can't return a value from an initializer.",
+                        );
+                    }
+
+                    self.resolve_expr(value);
+                }
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => (),
+            ExprKind::Grouping { expression } => self.resolve_expr(expression),
+            ExprKind::Unary { right, .. } => self.resolve_expr(right),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Variable { token, depth } => {
+                if self
+                    .scopes
+                    .last()
+                    .is_some_and(|scope| scope.get(&token.lexeme) == Some(&false))
+                {
+                    self.error(token, "Can't read local variable in its own initializer.");
+                }
+
+                self.resolve_local(token, depth);
+            }
+            ExprKind::Assign {
+                token,
+                value,
+                depth,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_local(token, depth);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                arguments
+                    .iter()
+                    .for_each(|argument| self.resolve_expr(argument));
+            }
+            ExprKind::Get { object, .. } => self.resolve_expr(object),
+            ExprKind::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            ExprKind::List(elements) => elements
+                .iter()
+                .for_each(|element| self.resolve_expr(element)),
+            ExprKind::Map { entries, .. } => entries.iter().for_each(|(key, value)| {
+                self.resolve_expr(key);
+                self.resolve_expr(value);
+            }),
+            ExprKind::GetIndex { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            ExprKind::This { keyword, depth } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+
+                self.resolve_local(keyword, depth);
+            }
+            ExprKind::Super { keyword, depth, .. } => match self.current_class {
+                ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                ClassType::Class => {
+                    self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                }
+                ClassType::Subclass => self.resolve_local(keyword, depth),
+            },
+        }
+    }
+
+    fn resolve_function(&mut self, function: &FunDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_decls(&function.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_local(&mut self, token: &Token, depth: &Depth) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&token.lexeme));
+
+        depth.set(found);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.lexeme.clone(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(SyntaxError::at_token(token, message));
+    }
+
+    /// Like [`Resolver::error`], but underlines all of `span` rather than
+    /// just `token`.
+    fn error_spanning(&mut self, token: &Token, span: Span, message: &str) {
+        let mut error = SyntaxError::at_token(token, message);
+        error.span = span;
+        self.errors.push(error);
+    }
+}
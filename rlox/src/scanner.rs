@@ -1,20 +1,37 @@
+use std::mem::take;
+
 use crate::{
-    token::{Token, TokenType},
-    Lox,
+    error::SyntaxError,
+    token::{Span, Token, TokenType},
 };
 
+/// Turns source text into tokens. It works a `char` at a time; `start` and
+/// `current` are byte offsets into `source`, always on char boundaries.
 pub struct Scanner<'a> {
-    source: &'a [u8],
+    source: &'a str,
     tokens: Vec<Token>,
+    errors: Vec<SyntaxError>,
     start: usize,
     current: usize,
     line: u64,
+    /// Column of the char at `current`, counted in chars as it advances so
+    /// that long lines don't have to be rescanned.
+    column: u64,
+    /// Line and column of the char at `start`, where the token being scanned
+    /// began. A string or block comment can end on a later line.
+    start_line: u64,
+    start_column: u64,
+    /// `///` comment lines seen since the last token, waiting to be attached
+    /// to the next one.
+    doc: Option<String>,
 }
 
 fn map_keywords(string: &str) -> Option<TokenType> {
     match string {
         "and" => Some(TokenType::And),
+        "break" => Some(TokenType::Break),
         "class" => Some(TokenType::Class),
+        "continue" => Some(TokenType::Continue),
         "else" => Some(TokenType::Else),
         "false" => Some(TokenType::False),
         "for" => Some(TokenType::For),
@@ -33,142 +50,291 @@ fn map_keywords(string: &str) -> Option<TokenType> {
     }
 }
 
+#[cfg(not(feature = "unicode-identifiers"))]
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+#[cfg(not(feature = "unicode-identifiers"))]
+fn is_identifier_continue(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(feature = "unicode-identifiers")]
+fn is_identifier_start(c: char) -> bool {
+    unicode_ident::is_xid_start(c) || c == '_'
+}
+
+#[cfg(feature = "unicode-identifiers")]
+fn is_identifier_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Scanner {
-            source: source.as_bytes(),
+            source,
             tokens: Vec::new(),
+            errors: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            doc: None,
+        }
+    }
+
+    pub fn scan_tokens(&mut self) -> Result<&[Token], Vec<SyntaxError>> {
+        let (tokens, errors) = self.scan_tokens_and_errors();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &[Token] {
+    /// Like [`Scanner::scan_tokens`], but returns the tokens even when some
+    /// of the source couldn't be scanned, along with the errors for the parts
+    /// that were skipped, so that the tokens can still be parsed for errors.
+    pub fn scan_tokens_and_errors(&mut self) -> (&[Token], Vec<SyntaxError>) {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column(self.start);
             self.scan_token();
         }
 
-        self.tokens
-            .push(Token::new(TokenType::Eof, "".to_string(), self.line));
+        self.tokens.push(Token::new(
+            TokenType::Eof,
+            "".to_string(),
+            self.line,
+            self.column(self.current),
+            Span::new(self.current, self.current),
+        ));
 
-        &self.tokens
+        let errors = take(&mut self.errors);
+        (&self.tokens, errors)
     }
 
     fn scan_token(&mut self) {
         let c = self.advance();
         match c {
-            b'(' => self.add_token(TokenType::LeftParen),
-            b')' => self.add_token(TokenType::RightParen),
-            b'{' => self.add_token(TokenType::LeftBrace),
-            b'}' => self.add_token(TokenType::RightBrace),
-            b',' => self.add_token(TokenType::Comma),
-            b'.' => self.add_token(TokenType::Dot),
-            b'-' => self.add_token(TokenType::Minus),
-            b'+' => self.add_token(TokenType::Plus),
-            b';' => self.add_token(TokenType::Semicolon),
-            b'*' => self.add_token(TokenType::Star),
-            b'!' => {
-                let token = if self.match_next(b'=') {
+            '(' => self.add_token(TokenType::LeftParen),
+            ')' => self.add_token(TokenType::RightParen),
+            '{' => self.add_token(TokenType::LeftBrace),
+            '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
+            ',' => self.add_token(TokenType::Comma),
+            ':' => self.add_token(TokenType::Colon),
+            '.' => self.add_token(TokenType::Dot),
+            '-' => self.add_token(TokenType::Minus),
+            '+' => self.add_token(TokenType::Plus),
+            ';' => self.add_token(TokenType::Semicolon),
+            '*' => self.add_token(TokenType::Star),
+            '!' => {
+                let token = if self.match_next('=') {
                     TokenType::BangEqual
                 } else {
                     TokenType::Bang
                 };
                 self.add_token(token)
             }
-            b'=' => {
-                let token = if self.match_next(b'=') {
+            '=' => {
+                let token = if self.match_next('=') {
                     TokenType::EqualEqual
                 } else {
                     TokenType::Equal
                 };
                 self.add_token(token)
             }
-            b'<' => {
-                let token = if self.match_next(b'=') {
+            '<' => {
+                let token = if self.match_next('=') {
                     TokenType::LessEqual
                 } else {
                     TokenType::Less
                 };
                 self.add_token(token)
             }
-            b'>' => {
-                let token = if self.match_next(b'=') {
+            '>' => {
+                let token = if self.match_next('=') {
                     TokenType::GreaterEqual
                 } else {
                     TokenType::Greater
                 };
                 self.add_token(token)
             }
-            b'/' => {
-                if self.match_next(b'/') {
-                    while self.peek() != b'\n' && !self.is_at_end() {
-                        self.advance();
-                    }
+            '/' => {
+                if self.match_next('/') {
+                    self.line_comment();
+                } else if self.match_next('*') {
+                    self.block_comment();
                 } else {
                     self.add_token(TokenType::Slash);
                 }
             }
-            b' ' => (),
-            b'\r' => (),
-            b'\t' => (),
-            b'\n' => self.line += 1,
-            b'"' => self.string(),
-            b'0'..=b'9' => self.number(),
-            b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.identifier(),
-            _ => Lox::error(self.line, "Unexpected character"),
+            ' ' => (),
+            '\r' => (),
+            '\t' => (),
+            '\n' => self.new_line(),
+            '"' => self.string(),
+            '0'..='9' => self.number(),
+            c if is_identifier_start(c) => self.identifier(),
+            _ => self.error("Unexpected character"),
         }
     }
 
-    fn advance(&mut self) -> u8 {
-        self.current += 1;
-        self.source[self.current - 1]
+    fn advance(&mut self) -> char {
+        let c = self.peek();
+        self.current += c.len_utf8();
+        self.column += 1;
+        c
     }
 
-    fn match_next(&mut self, expected: u8) -> bool {
-        if self.is_at_end() {
+    fn match_next(&mut self, expected: char) -> bool {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
 
-        if self.source[self.current] != expected {
-            return false;
-        }
-
-        self.current += 1;
+        self.current += expected.len_utf8();
+        self.column += 1;
         true
     }
 
-    fn peek(&self) -> u8 {
-        if self.is_at_end() {
-            return b'\0';
+    fn peek(&self) -> char {
+        self.source[self.current..].chars().next().unwrap_or('\0')
+    }
+
+    fn peek_next(&self) -> char {
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
+    }
+
+    /// Skips the rest of a `//` comment, keeping its text if it is a `///` doc
+    /// comment. Like in Rust, `////` starts an ordinary comment.
+    fn line_comment(&mut self) {
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+
+        let comment = &self.source[self.start + 2..self.current];
+        if let Some(text) = comment.strip_prefix('/')
+            && !text.starts_with('/')
+        {
+            let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+            match &mut self.doc {
+                Some(doc) => {
+                    doc.push('\n');
+                    doc.push_str(text);
+                }
+                None => self.doc = Some(text.to_owned()),
+            }
         }
-        self.source[self.current]
     }
 
-    fn peek_next(&self) -> u8 {
-        *self.source.get(self.current + 1).unwrap_or(&b'\0')
+    /// Skips a `/* ... */` comment, which may contain other block comments.
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                self.error("Unterminated block comment.");
+                return;
+            }
+
+            match self.advance() {
+                '/' if self.match_next('*') => depth += 1,
+                '*' if self.match_next('/') => depth -= 1,
+                '\n' => self.new_line(),
+                _ => (),
+            }
+        }
     }
 
     fn string(&mut self) {
-        while self.peek() != b'"' && !self.is_at_end() {
-            if self.peek() == b'\n' {
-                self.line += 1;
+        let mut value = String::new();
+
+        while self.peek() != '"' && !self.is_at_end() {
+            match self.advance() {
+                '\\' => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                '\n' => {
+                    self.new_line();
+                    value.push('\n');
+                }
+                c => value.push(c),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            Lox::error(self.line, "Unterminated string.");
+            self.error("Unterminated string.");
             return;
         }
 
         self.advance();
+        self.add_token(TokenType::String(value));
+    }
+
+    /// Scans the rest of an escape sequence after its `\`, reporting an error
+    /// and returning `None` if it isn't valid.
+    fn escape(&mut self) -> Option<char> {
+        let start = self.current - 1;
+        let escaped = match self.peek() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            'u' => {
+                self.advance();
+                return self.unicode_escape(start);
+            }
+            // Leave the unterminated string to be reported by the caller.
+            '\0' if self.is_at_end() => return None,
+            c => {
+                self.advance();
+                self.error_at(start, &format!("Invalid escape sequence '\\{c}'."));
+                // The string goes on after a bad escape, so lines must still
+                // be counted.
+                if c == '\n' {
+                    self.new_line();
+                }
+                return None;
+            }
+        };
+
+        self.advance();
+        Some(escaped)
+    }
 
-        let value =
-            String::from_utf8(self.source[self.start + 1..self.current - 1].to_vec()).unwrap();
+    /// Scans the `{XXXX}` of a `\u{XXXX}` escape: one to six hex digits naming
+    /// a Unicode scalar value.
+    fn unicode_escape(&mut self, start: usize) -> Option<char> {
+        let mut escaped = None;
 
-        self.add_token(TokenType::String(value));
+        if self.match_next('{') {
+            let digits_start = self.current;
+            while self.peek().is_ascii_hexdigit() {
+                self.advance();
+            }
+            let digits = &self.source[digits_start..self.current];
+
+            if (1..=6).contains(&digits.len()) && self.match_next('}') {
+                escaped = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32);
+            }
+        }
+
+        if escaped.is_none() {
+            self.error_at(start, "Invalid Unicode escape sequence.");
+        }
+        escaped
     }
 
     fn number(&mut self) {
@@ -176,7 +342,7 @@ impl<'a> Scanner<'a> {
             self.advance();
         }
 
-        if self.peek() == b'.' && self.peek_next().is_ascii_digit() {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
             while self.peek().is_ascii_digit() {
@@ -185,32 +351,218 @@ impl<'a> Scanner<'a> {
         }
 
         self.add_token(TokenType::Number(
-            String::from_utf8(self.source[self.start..self.current].to_vec())
-                .unwrap()
-                .parse()
-                .unwrap(),
+            self.source[self.start..self.current].parse().unwrap(),
         ));
     }
 
     fn identifier(&mut self) {
-        while self.peek().is_ascii_alphanumeric() {
+        while is_identifier_continue(self.peek()) {
             self.advance();
         }
 
-        let text = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
-        match map_keywords(&text) {
+        let text = &self.source[self.start..self.current];
+        match map_keywords(text) {
             Some(token_type) => self.add_token(token_type),
-            None => self.add_token(TokenType::Identifier(text)),
+            None => self.add_token(TokenType::Identifier(text.to_owned())),
         }
     }
 
     fn add_token(&mut self, token_type: TokenType) {
-        let text = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
+        let mut token = Token::new(
+            token_type,
+            self.source[self.start..self.current].to_owned(),
+            self.line,
+            self.start_column,
+            Span::new(self.start, self.current),
+        );
+        token.doc = self.doc.take();
+        self.tokens.push(token);
+    }
+
+    /// Called just after consuming a `\n`.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    /// Column of the char at byte offset `at`, which must be on the current
+    /// line and no later than `current`. It counts back from `current`, so it
+    /// is cheap for the nearby offsets it is used for.
+    fn column(&self, at: usize) -> u64 {
+        self.column - self.source[at..self.current].chars().count() as u64
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors.push(SyntaxError::new(
+            self.start_line,
+            self.start_column,
+            Span::new(self.start, self.current),
+            message,
+        ));
+    }
 
-        self.tokens.push(Token::new(token_type, text, self.line));
+    /// Reports an error covering `start..current` on the current line, for
+    /// problems inside a token such as a bad escape in a string.
+    fn error_at(&mut self, start: usize, message: &str) {
+        self.errors.push(SyntaxError::new(
+            self.line,
+            self.column(start),
+            Span::new(start, self.current),
+            message,
+        ));
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<TokenType> {
+        Scanner::new(source)
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|token| token.token_type.clone())
+            .collect()
+    }
+
+    fn identifier(name: &str) -> TokenType {
+        TokenType::Identifier(name.to_owned())
+    }
+
+    #[test]
+    fn identifiers_can_contain_underscores_and_digits() {
+        assert_eq!(
+            scan("my_var _private __ a1_b2 x_"),
+            [
+                identifier("my_var"),
+                identifier("_private"),
+                identifier("__"),
+                identifier("a1_b2"),
+                identifier("x_"),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_cannot_start_with_a_digit() {
+        assert_eq!(
+            scan("1_a"),
+            [TokenType::Number(1.0), identifier("_a"), TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn keywords_are_only_matched_whole() {
+        assert_eq!(
+            scan("var var_ _var variable"),
+            [
+                TokenType::Var,
+                identifier("var_"),
+                identifier("_var"),
+                identifier("variable"),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn identifier_spans_are_in_bytes_and_columns_in_chars() {
+        let mut scanner = Scanner::new("\"é\" snake_case");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[1].lexeme, "snake_case");
+        assert_eq!(tokens[1].span, Span::new(5, 15));
+        assert_eq!(tokens[1].column, 5);
+    }
+
+    #[test]
+    fn block_comments_nest_and_count_lines() {
+        let mut scanner = Scanner::new("a /* one /* two\n */ still\n */ b");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[1].token_type, identifier("b"));
+        assert_eq!((tokens[1].line, tokens[1].column), (3, 5));
+    }
+
+    #[test]
+    fn unterminated_block_comments_are_errors() {
+        let errors = Scanner::new("a /* /* */").scan_tokens().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated block comment.");
+        assert_eq!((errors[0].line, errors[0].column), (1, 3));
+    }
+
+    #[test]
+    fn unterminated_tokens_are_reported_on_the_line_they_start() {
+        let errors = Scanner::new("a;\nprint \"one\ntwo\nthree")
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(errors[0].message, "Unterminated string.");
+        assert_eq!((errors[0].line, errors[0].column), (2, 7));
+
+        let errors = Scanner::new("a;\n  /* one\ntwo\n")
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(errors[0].message, "Unterminated block comment.");
+        assert_eq!((errors[0].line, errors[0].column), (2, 3));
+    }
+
+    #[test]
+    fn escaped_newlines_still_count_as_lines() {
+        let errors = Scanner::new("\"a\\\nb\";\n@").scan_tokens().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (1, 3));
+        assert_eq!(errors[1].message, "Unexpected character");
+        assert_eq!((errors[1].line, errors[1].column), (3, 1));
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_token() {
+        let mut scanner =
+            Scanner::new("/// First.\n///   Second.\n//// Not a doc.\nvar x; // Nor this.\nx;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].doc.as_deref(), Some("First.\n  Second."));
+        assert!(tokens[1..].iter().all(|token| token.doc.is_none()));
+    }
+
+    #[cfg(not(feature = "unicode-identifiers"))]
+    #[test]
+    fn non_ascii_identifiers_are_rejected() {
+        let errors = Scanner::new("var café;").scan_tokens().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unexpected character");
+        assert_eq!(errors[0].column, 8);
+    }
+
+    #[cfg(feature = "unicode-identifiers")]
+    #[test]
+    fn unicode_identifiers_are_accepted() {
+        assert_eq!(
+            scan("café 変数 _ñ"),
+            [
+                identifier("café"),
+                identifier("変数"),
+                identifier("_ñ"),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[cfg(feature = "unicode-identifiers")]
+    #[test]
+    fn unicode_identifiers_must_start_with_xid_start() {
+        let errors = Scanner::new("\u{300}a").scan_tokens().unwrap_err();
+
+        assert_eq!(errors[0].message, "Unexpected character");
+    }
+}
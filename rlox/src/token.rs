@@ -6,7 +6,10 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -25,10 +28,12 @@ pub enum TokenType {
 
     Identifier(String),
     String(String),
-    Number(i64),
+    Number(f64),
 
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -47,19 +52,40 @@ pub enum TokenType {
     Eof,
 }
 
+/// Byte range `start..end` of a piece of source text.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: u64,
+    pub column: u64,
+    pub span: Span,
+    /// Text of the `///` doc comment lines directly before this token, if
+    /// any, without their `///` markers.
+    pub doc: Option<String>,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: u64) -> Token {
+    pub fn new(token_type: TokenType, lexeme: String, line: u64, column: u64, span: Span) -> Token {
         Token {
             token_type,
             lexeme,
             line,
+            column,
+            span,
+            doc: None,
         }
     }
 }
@@ -0,0 +1,63 @@
+use rlox::{DocComment, DocKind, LoxError, doc_comments};
+
+#[test]
+fn doc_comments_are_collected_from_declarations() {
+    let source = r#"
+/// The answer.
+var answer = 42;
+
+// An ordinary comment.
+var undocumented = 1;
+
+/// A point in the plane.
+///
+/// Both coordinates are numbers.
+class Point {
+  /// Makes a point.
+  init(x, y) {
+    this.x = x;
+    this.y = y;
+  }
+
+  norm() {
+    return sqrt(this.x * this.x + this.y * this.y);
+  }
+}
+
+/// Says hello.
+fun greet(name) {
+  /// Nested declarations aren't collected.
+  var greeting = "hello, " + name;
+  print greeting;
+}
+"#;
+
+    let doc = |kind, name: &str, line, text: &str| DocComment {
+        kind,
+        name: name.to_owned(),
+        line,
+        text: text.to_owned(),
+    };
+    assert_eq!(
+        doc_comments(source).unwrap(),
+        [
+            doc(DocKind::Variable, "answer", 3, "The answer."),
+            doc(
+                DocKind::Class,
+                "Point",
+                11,
+                "A point in the plane.\n\nBoth coordinates are numbers."
+            ),
+            doc(DocKind::Method, "Point.init", 13, "Makes a point."),
+            doc(DocKind::Function, "greet", 24, "Says hello."),
+        ]
+    );
+}
+
+#[test]
+fn doc_comments_require_a_valid_program() {
+    assert!(matches!(
+        doc_comments("/// Broken.\nfun f( {}"),
+        Err(LoxError::Parse(_))
+    ));
+}
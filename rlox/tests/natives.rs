@@ -0,0 +1,93 @@
+use std::io;
+
+use rlox::{Lox, LoxError, Value};
+
+#[test]
+fn natives_can_be_called_from_lox() {
+    let mut output = Vec::new();
+
+    {
+        let mut lox = Lox::with_output(&mut output, io::sink());
+        lox.register_native("add", 2, |args| {
+            let a = f64::try_from(&args[0])?;
+            let b = f64::try_from(&args[1])?;
+            Ok(Value::from(a + b))
+        });
+        lox.register_native("greet", 1, |args| {
+            let name = String::try_from(&args[0])?;
+            Ok(Value::from(format!("hello, {name}")))
+        });
+        lox.run("print add(1, 2); print greet(\"lox\"); print add;")
+            .unwrap();
+    }
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "3\nhello, lox\n<native fn>\n"
+    );
+}
+
+#[test]
+fn native_arity_is_checked() {
+    let mut lox = Lox::with_output(io::sink(), io::sink());
+    lox.register_native("answer", 0, |_| Ok(Value::from(42.0)));
+
+    let Err(LoxError::Runtime(error)) = lox.run("answer(1);") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.message, "Expected 0 arguments but got 1.");
+    assert_eq!((error.line, error.column), (1, 9));
+}
+
+#[test]
+fn native_errors_become_runtime_errors_at_the_call() {
+    let mut lox = Lox::with_output(io::sink(), io::sink());
+    lox.register_native("double", 1, |args| {
+        Ok(Value::from(f64::try_from(&args[0])? * 2.0))
+    });
+
+    let result = lox.run("fun f() {\n  return double(\"two\");\n}\nf();");
+
+    let Err(LoxError::Runtime(error)) = result else {
+        panic!("expected a runtime error, got {result:?}");
+    };
+    assert_eq!(error.message, "Expected a number but got string.");
+    assert_eq!((error.line, error.column), (2, 22));
+    assert_eq!(
+        error
+            .trace
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        ["[line 2] in f()", "[line 4] in script"]
+    );
+}
+
+#[test]
+fn prelude_can_be_left_out() {
+    let mut lox = Lox::without_prelude(io::sink(), io::sink());
+
+    let Err(LoxError::Runtime(error)) = lox.run("clock();") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.message, "Undefined variable 'clock'.");
+
+    lox.register_native("clock", 0, |_| Ok(Value::from(0.0)));
+    lox.run("clock();").unwrap();
+}
+
+#[test]
+fn list_and_map_operations_are_always_defined() {
+    let mut output = Vec::new();
+    Lox::without_prelude(&mut output, io::sink())
+        .run(
+            "var l = [1]; append(l, 2); print pop(l); print len(l);
+             var m = {\"a\": 1}; print keys(m); print values(m); print has(m, \"b\");",
+        )
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "2\n1\n[a]\n[1]\nfalse\n"
+    );
+}
@@ -0,0 +1,110 @@
+use std::io;
+
+use rlox::{Lox, LoxError, Value};
+
+#[test]
+fn print_writes_to_the_given_output() {
+    let mut output = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    {
+        let mut lox = Lox::with_output(&mut output, &mut diagnostics);
+        lox.run("var greeting = \"hello\";").unwrap();
+        lox.run("print greeting; print 1 + 2;").unwrap();
+    }
+
+    assert_eq!(String::from_utf8(output).unwrap(), "hello\n3\n");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn large_and_small_numbers_print_in_exponent_notation() {
+    let printed = |number: f64| Value::from(number).to_string();
+
+    assert_eq!(printed(1e300), "1e+300");
+    assert_eq!(printed(-2.5e-300), "-2.5e-300");
+    assert_eq!(printed(1e16), "10000000000000000");
+    assert_eq!(printed(1e17), "1e+17");
+    assert_eq!(printed(0.0001), "0.0001");
+    assert_eq!(printed(0.00001), "1e-05");
+    assert_eq!(printed(-0.0), "-0");
+}
+
+#[test]
+fn errors_are_reported_to_diagnostics_and_returned() {
+    let mut output = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    let result = Lox::with_output(&mut output, &mut diagnostics).run("print 1;\nprint -nil;");
+
+    assert!(matches!(result, Err(LoxError::Runtime(_))));
+    assert_eq!(String::from_utf8(output).unwrap(), "1\n");
+    assert_eq!(
+        String::from_utf8(diagnostics).unwrap(),
+        "Operand must be a number.\n[line 2] in script\n"
+    );
+}
+
+#[test]
+fn diagnostics_can_be_discarded() {
+    let mut output = Vec::new();
+
+    let result = Lox::with_output(&mut output, io::sink()).run("print ;");
+
+    let Err(LoxError::Parse(errors)) = result else {
+        panic!("expected a parse error, got {result:?}");
+    };
+    assert_eq!(errors[0].message, "Expect expression.");
+    assert!(output.is_empty());
+}
+
+#[test]
+fn scope_errors_underline_the_whole_statement_or_value() {
+    let source = "return 1 + 2;\nclass A { init() { return \"no\"; } }";
+
+    let result = Lox::with_output(io::sink(), io::sink()).run(source);
+
+    let Err(error @ LoxError::Resolve(_)) = result else {
+        panic!("expected a resolve error, got {result:?}");
+    };
+    assert_eq!(
+        Lox::report(source, &error),
+        "[line 1] Error at 'return': Can't return from top-level code.\n  |\n1 | return 1 + 2;\n  | ^^^^^^^^^^^^^\n\
+         [line 2] Error at 'return': Can't return a value from an initializer.\n  |\n2 | class A { init() { return \"no\"; } }\n  |                           ^^^^"
+    );
+}
+
+#[test]
+fn unbounded_recursion_is_a_runtime_error() {
+    let result = Lox::with_output(io::sink(), io::sink())
+        .run("fun f(n) {\n  return 1 + f(n + 1);\n}\nf(0);");
+
+    let Err(LoxError::Runtime(error)) = result else {
+        panic!("expected a runtime error, got {result:?}");
+    };
+    assert_eq!(error.message, "Stack overflow.");
+    assert_eq!(error.line, 2);
+    // Every call to f, then the script.
+    assert_eq!(error.trace.len(), 257);
+}
+
+#[test]
+fn scan_errors_do_not_stop_the_parser() {
+    let result = Lox::with_output(io::sink(), io::sink()).run("print 1 @ 2;\nprint ;");
+
+    let Err(LoxError::Parse(errors)) = result else {
+        panic!("expected parse errors, got {result:?}");
+    };
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|error| (error.line, error.message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (1, "Unexpected character"),
+            (1, "Expect ';' after value."),
+            (2, "Expect expression."),
+        ]
+    );
+}
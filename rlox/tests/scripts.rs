@@ -0,0 +1,206 @@
+//! Runs every `.lox` script under the repository's `tests/` directory and
+//! compares what it prints against the annotations in its comments.
+//!
+//! The annotations follow the craftinginterpreters test suite, so its scripts
+//! can be dropped in unchanged:
+//!
+//! - `// expect: <text>` — the next line printed to stdout.
+//! - `// expect runtime error: <message>` — the script stops with `<message>`
+//!   raised on this line, exiting with code 70.
+//! - `// Error at '<lexeme>': <message>` or `// [line N] Error...` — a compile
+//!   error reported on this line (or line N), exiting with code 65.
+//!   `// [java line N]` expectations apply to us too; `// [c line N]` ones are
+//!   specific to clox and ignored.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const SCRIPTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests");
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<String>,
+    runtime_error: Option<(String, usize)>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Expectations::default();
+        let mut in_string = false;
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let Some(comment) = comment(line, &mut in_string) else {
+                continue;
+            };
+
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_owned());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((message.to_owned(), line_number));
+            } else if comment.starts_with("Error") {
+                expectations
+                    .compile_errors
+                    .push(format!("[line {line_number}] {comment}"));
+            } else if let Some(rest) = comment
+                .strip_prefix("[line ")
+                .or_else(|| comment.strip_prefix("[java line "))
+            {
+                expectations.compile_errors.push(format!("[line {rest}"));
+            }
+        }
+
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+/// Returns the text after the `// ` that starts a comment on `line`, if any.
+/// `in_string` says whether the line starts inside a string literal, and is
+/// updated for the next line, since strings can span lines.
+fn comment<'a>(line: &'a str, in_string: &mut bool) -> Option<&'a str> {
+    let mut chars = line.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '"' => *in_string = !*in_string,
+            // Skip the escaped char, which may be a quote.
+            '\\' if *in_string => {
+                chars.next();
+            }
+            '/' if !*in_string && line[at..].starts_with("//") => {
+                return line[at + 2..].strip_prefix(' ');
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+#[test]
+fn comments_inside_strings_are_not_annotations() {
+    let expectations = Expectations::parse(
+        "print \"a // b\"; // expect: a // b\n\
+         print \"\\\" // c\"; // expect: \" // c\n\
+         print \"d\n// e\"; // expect: d\n\
+         print \"f\"; //expect: nothing",
+    );
+
+    assert_eq!(expectations.output, ["a // b", "\" // c", "d"]);
+}
+
+fn collect_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("could not read {}: {err}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_scripts(&path, scripts);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            scripts.push(path);
+        }
+    }
+}
+
+/// Runs one script and returns a description of every way its behaviour
+/// differed from its annotations.
+fn check_script(path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path).unwrap();
+    let expected = Expectations::parse(&source);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg(path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut failures = Vec::new();
+
+    let printed: Vec<&str> = stdout.lines().collect();
+    if printed != expected.output {
+        failures.push(format!(
+            "expected output {:?}\n    got {:?}",
+            expected.output, printed
+        ));
+    }
+
+    if let Some((message, line)) = &expected.runtime_error {
+        let mut lines = stderr.lines();
+        if lines.next() != Some(message.as_str()) {
+            failures.push(format!(
+                "expected runtime error {message:?}, got {stderr:?}"
+            ));
+        }
+        if !lines
+            .next()
+            .is_some_and(|trace| trace.starts_with(&format!("[line {line}]")))
+        {
+            failures.push(format!(
+                "expected runtime error on line {line}, got {stderr:?}"
+            ));
+        }
+    } else {
+        // Compile errors are followed by a source snippet; only the headers
+        // are part of the expected output.
+        let errors: Vec<&str> = stderr
+            .lines()
+            .filter(|line| line.starts_with("[line "))
+            .collect();
+        if errors != expected.compile_errors {
+            failures.push(format!(
+                "expected errors {:?}\n    got {stderr:?}",
+                expected.compile_errors
+            ));
+        }
+    }
+
+    let code = output.status.code();
+    if code != Some(expected.exit_code()) {
+        failures.push(format!(
+            "expected exit code {}, got {code:?}",
+            expected.exit_code()
+        ));
+    }
+
+    failures
+}
+
+#[test]
+fn scripts_match_their_expectations() {
+    let mut scripts = Vec::new();
+    collect_scripts(Path::new(SCRIPTS_DIR), &mut scripts);
+    assert!(!scripts.is_empty(), "no scripts found in {SCRIPTS_DIR}");
+
+    let failures: Vec<String> = scripts
+        .iter()
+        .flat_map(|path| {
+            check_script(path)
+                .into_iter()
+                .map(move |failure| format!("{}: {failure}", path.display()))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} failures across {} scripts:\n{}",
+        failures.len(),
+        scripts.len(),
+        failures.join("\n")
+    );
+}